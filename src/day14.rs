@@ -1,4 +1,10 @@
-use std::fmt::{Display, Write};
+use std::{
+    fmt::{Display, Write},
+    fs::File,
+    io::{self, BufWriter},
+    ops::Range,
+    path,
+};
 
 use aoc_runner_derive::aoc;
 
@@ -44,6 +50,7 @@ struct Grid {
     cols: usize,
     start: (usize, usize),
     data: Vec<Vec<u8>>,
+    floor: bool,
 }
 
 impl Display for Grid {
@@ -100,6 +107,7 @@ impl Grid {
             cols,
             start,
             data,
+            floor: false,
         }
     }
 
@@ -107,6 +115,7 @@ impl Grid {
         self.data.push(vec![b'.'; self.cols]);
         self.data.push(vec![b'#'; self.cols]);
         self.rows += 2;
+        self.floor = true;
     }
 
     fn lock_next(&mut self) -> AdvanceOutcome {
@@ -130,6 +139,264 @@ impl Grid {
             }
         }
     }
+
+    fn lock_next_memo(&mut self, path: &mut Vec<Coord>) -> AdvanceOutcome {
+        let (mut x, mut y) = match path.last() {
+            Some(last) => *last,
+            None => return AdvanceOutcome::StartBlocked,
+        };
+        loop {
+            if x + 1 == self.rows {
+                break AdvanceOutcome::InAbyss;
+            }
+            let next_points = [(x + 1, y), (x + 1, y.saturating_sub(1)), (x + 1, y + 1)];
+            if let Some(next) = next_points
+                .iter()
+                .find(|(x, y)| *y < self.cols && self.data[*x][*y] == b'.')
+            {
+                (x, y) = *next;
+                path.push(*next);
+            } else {
+                path.pop();
+                if (x, y) != self.start {
+                    self.data[x][y] = b'o';
+                    break AdvanceOutcome::Locked;
+                }
+                break AdvanceOutcome::StartBlocked;
+            }
+        }
+    }
+
+    fn flood_fill_count(&self) -> usize {
+        let mut reached = vec![false; self.cols];
+        reached[self.start.1] = true;
+        let mut count = 1;
+        for row in self.data.iter().take(self.rows - 1).skip(self.start.0 + 1) {
+            let next = (0..self.cols)
+                .map(|y| {
+                    row[y] != b'#'
+                        && (reached[y]
+                            || (y > 0 && reached[y - 1])
+                            || (y + 1 < self.cols && reached[y + 1]))
+                })
+                .collect::<Vec<_>>();
+            count += next.iter().filter(|r| **r).count();
+            reached = next;
+        }
+        count
+    }
+}
+
+const AIR_COLOR: [u8; 3] = [24, 28, 48];
+const ROCK_COLOR: [u8; 3] = [128, 128, 128];
+const SAND_COLOR: [u8; 3] = [230, 190, 90];
+const SOURCE_COLOR: [u8; 3] = [220, 40, 40];
+const ABYSS_COLOR: [u8; 3] = [0, 0, 0];
+const ABYSS_ROWS: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Ppm,
+}
+
+impl ImageFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Ppm => "ppm",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ExportOptions {
+    pub format: ImageFormat,
+    pub scale: usize,
+    pub floor: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            format: ImageFormat::Png,
+            scale: 4,
+            floor: false,
+        }
+    }
+}
+
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    fn write_ppm(&self, w: &mut impl io::Write) -> io::Result<()> {
+        write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+        w.write_all(&self.pixels)
+    }
+
+    fn write_png(&self, w: &mut impl io::Write) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(w, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+
+    fn write(&self, w: &mut impl io::Write, format: ImageFormat) -> io::Result<()> {
+        match format {
+            ImageFormat::Png => self.write_png(w),
+            ImageFormat::Ppm => self.write_ppm(w),
+        }
+    }
+
+    fn save(&self, file: &path::Path, format: ImageFormat) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(file)?);
+        self.write(&mut w, format)
+    }
+}
+
+impl Grid {
+    // Keeps the image size fixed across frames: rocks and source plus, with a
+    // floor, the widest possible sand pile.
+    fn view_cols(&self) -> Range<usize> {
+        let rock_cols = || {
+            self.data
+                .iter()
+                .take(if self.floor { self.rows - 1 } else { self.rows })
+                .flat_map(|row| row.iter().positions(|c| *c == b'#'))
+        };
+        let mut min_y = rock_cols().min().unwrap_or(self.start.1).min(self.start.1);
+        let mut max_y = rock_cols().max().unwrap_or(self.start.1).max(self.start.1);
+        if self.floor {
+            min_y = min_y.min(self.start.1.saturating_sub(self.rows));
+            max_y = max_y.max(self.start.1 + self.rows);
+        }
+        min_y.saturating_sub(1)..(max_y + 2).min(self.cols)
+    }
+
+    fn render(&self, cols: Range<usize>, scale: usize) -> Image {
+        let abyss_rows = if self.floor { 0 } else { ABYSS_ROWS };
+        let width = cols.len() * scale;
+        let height = (self.rows + abyss_rows) * scale;
+        let mut pixels = Vec::with_capacity(width * height * 3);
+        for x in 0..self.rows + abyss_rows {
+            let row = cols
+                .clone()
+                .flat_map(|y| {
+                    let color = match self.data.get(x).map(|row| row[y]) {
+                        None => ABYSS_COLOR,
+                        Some(b'#') => ROCK_COLOR,
+                        Some(b'o') => SAND_COLOR,
+                        Some(b'+') => SOURCE_COLOR,
+                        Some(_) => AIR_COLOR,
+                    };
+                    std::iter::repeat_n(color, scale).flatten()
+                })
+                .collect::<Vec<_>>();
+            for _ in 0..scale {
+                pixels.extend_from_slice(&row);
+            }
+        }
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    fn is_done(&self, outcome: AdvanceOutcome) -> bool {
+        match outcome {
+            AdvanceOutcome::Locked => false,
+            AdvanceOutcome::InAbyss => !self.floor,
+            AdvanceOutcome::StartBlocked => true,
+        }
+    }
+
+    // Locks the next grain like `lock_next_memo`, also drawing the last one, which comes to
+    // rest on the source.
+    fn lock_shown(&mut self, path: &mut Vec<Coord>) -> AdvanceOutcome {
+        let outcome = self.lock_next_memo(path);
+        if outcome == AdvanceOutcome::StartBlocked {
+            self.data[self.start.0][self.start.1] = b'o';
+        }
+        outcome
+    }
+
+    fn lock_all(&mut self) {
+        let mut path = vec![self.start];
+        loop {
+            let outcome = self.lock_shown(&mut path);
+            if self.is_done(outcome) {
+                break;
+            }
+        }
+    }
+}
+
+fn export_grid(input: &str, options: &ExportOptions) -> Grid {
+    let paths = parse_input(input);
+    let mut grid = Grid::from_paths(&paths);
+    if options.floor {
+        grid.add_floor();
+    }
+    grid
+}
+
+pub fn export_image(input: &str, options: &ExportOptions, file: &path::Path) -> io::Result<()> {
+    let mut grid = export_grid(input, options);
+    grid.lock_all();
+    grid.render(grid.view_cols(), options.scale)
+        .save(file, options.format)
+}
+
+pub fn export_frames(
+    input: &str,
+    options: &ExportOptions,
+    every: usize,
+    dir: &path::Path,
+) -> io::Result<usize> {
+    if every == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Frame interval must be positive",
+        ));
+    }
+    std::fs::create_dir_all(dir)?;
+    let mut grid = export_grid(input, options);
+    let cols = grid.view_cols();
+    let mut path_stack = vec![grid.start];
+    let mut frames = 0;
+    let mut save_frame = |grid: &Grid| {
+        let name = format!("frame_{:05}.{}", frames, options.format.extension());
+        frames += 1;
+        grid.render(cols.clone(), options.scale)
+            .save(&dir.join(name), options.format)
+    };
+    save_frame(&grid)?;
+    let mut grains = 0;
+    let mut unsaved = false;
+    loop {
+        let outcome = grid.lock_shown(&mut path_stack);
+        if outcome != AdvanceOutcome::InAbyss {
+            grains += 1;
+            unsaved = grains % every != 0;
+            if !unsaved {
+                save_frame(&grid)?;
+            }
+        }
+        if grid.is_done(outcome) {
+            break;
+        }
+    }
+    if unsaved {
+        save_frame(&grid)?;
+    }
+    Ok(frames)
 }
 
 #[aoc(day14, part1)]
@@ -155,6 +422,39 @@ pub fn part2(input: &str) -> usize {
     i + 1
 }
 
+#[aoc(day14, part1, memo)]
+pub fn part1_memo(input: &str) -> usize {
+    let paths = parse_input(input);
+    let mut grid = Grid::from_paths(&paths);
+    let mut path = vec![grid.start];
+    let mut i = 0;
+    while grid.lock_next_memo(&mut path) != AdvanceOutcome::InAbyss {
+        i += 1;
+    }
+    i
+}
+
+#[aoc(day14, part2, memo)]
+pub fn part2_memo(input: &str) -> usize {
+    let paths = parse_input(input);
+    let mut grid = Grid::from_paths(&paths);
+    grid.add_floor();
+    let mut path = vec![grid.start];
+    let mut i = 0;
+    while grid.lock_next_memo(&mut path) != AdvanceOutcome::StartBlocked {
+        i += 1;
+    }
+    i + 1
+}
+
+#[aoc(day14, part2, flood_fill)]
+pub fn part2_flood_fill(input: &str) -> usize {
+    let paths = parse_input(input);
+    let mut grid = Grid::from_paths(&paths);
+    grid.add_floor();
+    grid.flood_fill_count()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -170,4 +470,99 @@ mod tests {
         let example = include_str!("examples/day14.txt");
         assert_eq!(part2(example), 93);
     }
+
+    #[test]
+    fn test_example_part1_memo() {
+        let example = include_str!("examples/day14.txt");
+        assert_eq!(part1_memo(example), 24);
+    }
+
+    #[test]
+    fn test_example_part2_memo() {
+        let example = include_str!("examples/day14.txt");
+        assert_eq!(part2_memo(example), 93);
+    }
+
+    #[test]
+    fn test_example_part2_flood_fill() {
+        let example = include_str!("examples/day14.txt");
+        assert_eq!(part2_flood_fill(example), 93);
+    }
+
+    #[test]
+    fn test_render_example() {
+        let example = include_str!("examples/day14.txt");
+        let mut grid = Grid::from_paths(&parse_input(example));
+        grid.lock_all();
+        let cols = grid.view_cols();
+        let image = grid.render(cols.clone(), 2);
+        assert_eq!(image.width, cols.len() * 2);
+        assert_eq!(image.height, (grid.rows + ABYSS_ROWS) * 2);
+        let pixel = |x: usize, y: usize| {
+            let offset = ((x * 2) * image.width + (y - cols.start) * 2) * 3;
+            &image.pixels[offset..offset + 3]
+        };
+        assert_eq!(pixel(grid.start.0, grid.start.1), SOURCE_COLOR);
+        assert_eq!(pixel(grid.rows, grid.start.1), ABYSS_COLOR);
+        let sand = grid.data.iter().flatten().filter(|c| **c == b'o').count();
+        let sand_pixels = image.pixels.chunks(3).filter(|p| *p == SAND_COLOR).count();
+        assert_eq!(sand_pixels, sand * 4);
+
+        let mut ppm = Vec::new();
+        image.write(&mut ppm, ImageFormat::Ppm).unwrap();
+        let header = format!("P6\n{} {}\n255\n", image.width, image.height);
+        assert!(ppm.starts_with(header.as_bytes()));
+        assert_eq!(ppm.len(), header.len() + image.pixels.len());
+
+        let mut encoded = Vec::new();
+        image.write(&mut encoded, ImageFormat::Png).unwrap();
+        let mut reader = png::Decoder::new(encoded.as_slice()).read_info().unwrap();
+        let mut decoded = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut decoded).unwrap();
+        assert_eq!(decoded, image.pixels);
+    }
+
+    #[test]
+    fn test_export_frames() {
+        let example = include_str!("examples/day14.txt");
+        let dir = std::env::temp_dir().join(format!("day14-frames-{}", std::process::id()));
+        let options = ExportOptions {
+            format: ImageFormat::Ppm,
+            scale: 1,
+            floor: true,
+        };
+        let frames = export_frames(example, &options, 10, &dir).unwrap();
+        // Initial frame, one per 10 of the 93 grains, and the final state.
+        assert_eq!(frames, 11);
+        assert!(dir.join("frame_00010.ppm").exists());
+        // The last frame shows the grain resting on the source.
+        let last = std::fs::read(dir.join("frame_00010.ppm")).unwrap();
+        let header_len = last
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b == b'\n')
+            .nth(2)
+            .unwrap()
+            .0;
+        let pixels = &last[header_len + 1..];
+        let sand_pixels = pixels.chunks(3).filter(|p| *p == SAND_COLOR).count();
+        let source_pixels = pixels.chunks(3).filter(|p| *p == SOURCE_COLOR).count();
+        assert_eq!(sand_pixels, 93);
+        assert_eq!(source_pixels, 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let err = export_frames(example, &options, 0, &dir).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_lock_all_shows_last_grain() {
+        let example = include_str!("examples/day14.txt");
+        let mut grid = Grid::from_paths(&parse_input(example));
+        grid.add_floor();
+        grid.lock_all();
+        assert_eq!(grid.data[grid.start.0][grid.start.1], b'o');
+        let sand = grid.data.iter().flatten().filter(|c| **c == b'o').count();
+        assert_eq!(sand, 93);
+    }
 }
@@ -14,16 +14,63 @@ lazy_static! {
             .unwrap();
 }
 
-type Coord = (isize, isize);
+pub type Coord = (isize, isize);
+
+const HEADER_PREFIX: char = '#';
+
+// Puzzle parameters, overridable by `# key=value` header lines in the input,
+// e.g. `# row=10 limit=20` for the example.
+#[derive(Debug, PartialEq, Eq)]
+struct Params {
+    row: isize,
+    limit: isize,
+    frequency: isize,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            row: 2_000_000,
+            limit: 4_000_000,
+            frequency: 4_000_000,
+        }
+    }
+}
+
+impl Params {
+    fn parse(input: &str) -> Params {
+        let mut params = Params::default();
+        let assignments = input
+            .lines()
+            .filter_map(|line| line.strip_prefix(HEADER_PREFIX))
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty());
+        for assignment in assignments {
+            let (key, value) = assignment
+                .split_once('=')
+                .unwrap_or_else(|| panic!("Wrong parameter: {}", assignment));
+            let value = value
+                .parse::<isize>()
+                .unwrap_or_else(|_| panic!("Wrong parameter value: {}", assignment));
+            match key {
+                "row" => params.row = value,
+                "limit" => params.limit = value,
+                "frequency" => params.frequency = value,
+                _ => panic!("Unknown parameter: {}", key),
+            }
+        }
+        params
+    }
+}
 
 #[derive(Debug)]
-struct Sensor {
-    pos: Coord,
-    beacon_pos: Coord,
+pub struct Sensor {
+    pub pos: Coord,
+    pub beacon_pos: Coord,
 }
 
 impl Sensor {
-    fn parse(input: &str) -> Sensor {
+    pub fn parse(input: &str) -> Sensor {
         let captures = RE.captures(input).unwrap();
         let parse_capture = |index| {
             captures
@@ -39,78 +86,264 @@ impl Sensor {
         }
     }
 
-    fn beacon_distance(&self) -> isize {
+    pub fn beacon_distance(&self) -> isize {
         (self.pos.0 - self.beacon_pos.0).abs() + (self.pos.1 - self.beacon_pos.1).abs()
     }
-}
 
-fn blocked_interval(sensor: &Sensor, y_pos: isize) -> Option<IntervalSet<isize>> {
-    let max_distance = sensor.beacon_distance();
-    let (s_x, s_y) = sensor.pos;
-    let y_distance = (s_y - y_pos).abs();
-    if y_distance > max_distance {
-        None
-    } else {
-        let x_distance = max_distance - y_distance;
-        Some(IntervalSet::new(s_x - x_distance, s_x + x_distance))
+    pub fn covers(&self, (x, y): Coord) -> bool {
+        (self.pos.0 - x).abs() + (self.pos.1 - y).abs() <= self.beacon_distance()
+    }
+
+    // Covered cells on a line `distance` away from the sensor, centered on `center`.
+    fn blocked_interval(&self, center: isize, distance: isize) -> Option<IntervalSet<isize>> {
+        let max_distance = self.beacon_distance();
+        if distance > max_distance {
+            None
+        } else {
+            let reach = max_distance - distance;
+            Some(IntervalSet::new(center - reach, center + reach))
+        }
     }
 }
 
-fn blocked_intervals(sensors: &[Sensor], y_pos: isize) -> IntervalSet<isize> {
-    sensors
-        .iter()
-        .flat_map(|s| blocked_interval(s, y_pos))
-        .fold(IntervalSet::empty(), |acc, next| acc.union(&next))
+// Manhattan diamonds become axis-aligned squares in (u, v) = (x + y, x - y).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Rect {
+    u: (isize, isize),
+    v: (isize, isize),
 }
 
-fn part1_impl(input: &str, y_pos: isize) -> usize {
-    let sensors: Vec<Sensor> = input.lines().map(Sensor::parse).collect();
-    let blocked_intervals = blocked_intervals(&sensors, y_pos);
-    let beacon_intervals = sensors
-        .iter()
-        .flat_map(|s| {
-            let (s_x, s_y) = s.beacon_pos;
-            if s_y == y_pos {
-                Some(IntervalSet::singleton(s_x))
-            } else {
-                None
-            }
+impl Rect {
+    fn covering(sensor: &Sensor) -> Rect {
+        let (s_x, s_y) = sensor.pos;
+        let d = sensor.beacon_distance();
+        Rect {
+            u: (s_x + s_y - d, s_x + s_y + d),
+            v: (s_x - s_y - d, s_x - s_y + d),
+        }
+    }
+
+    fn intersects(&self, other: &Rect) -> bool {
+        self.u.0 <= other.u.1
+            && other.u.0 <= self.u.1
+            && self.v.0 <= other.v.1
+            && other.v.0 <= self.v.1
+    }
+
+    fn subtract(&self, other: &Rect) -> Vec<Rect> {
+        if !self.intersects(other) {
+            return vec![*self];
+        }
+        let mut parts = Vec::with_capacity(4);
+        if self.u.0 < other.u.0 {
+            parts.push(Rect {
+                u: (self.u.0, other.u.0 - 1),
+                v: self.v,
+            });
+        }
+        if other.u.1 < self.u.1 {
+            parts.push(Rect {
+                u: (other.u.1 + 1, self.u.1),
+                v: self.v,
+            });
+        }
+        let u = (self.u.0.max(other.u.0), self.u.1.min(other.u.1));
+        if self.v.0 < other.v.0 {
+            parts.push(Rect {
+                u,
+                v: (self.v.0, other.v.0 - 1),
+            });
+        }
+        if other.v.1 < self.v.1 {
+            parts.push(Rect {
+                u,
+                v: (other.v.1 + 1, self.v.1),
+            });
+        }
+        parts
+    }
+
+    // For every u of the rectangle that meets the box, the v range of lattice
+    // points (x, y) that also lie in the box, starting at the right parity.
+    fn v_ranges_in_box(
+        &self,
+        (min_x, min_y): Coord,
+        (max_x, max_y): Coord,
+    ) -> impl Iterator<Item = (isize, isize, isize)> {
+        let (v0, v1) = self.v;
+        let u_from = self
+            .u
+            .0
+            .max(v0 + 2 * min_y)
+            .max(2 * min_x - v1)
+            .max(min_x + min_y);
+        let u_to = self
+            .u
+            .1
+            .min(2 * max_x - v0)
+            .min(v1 + 2 * max_y)
+            .min(max_x + max_y);
+        (u_from..=u_to).map(move |u| {
+            let v_from = v0.max(2 * min_x - u).max(u - 2 * max_y);
+            let v_to = v1.min(2 * max_x - u).min(u - 2 * min_y);
+            (u, v_from + (u + v_from).rem_euclid(2), v_to)
         })
-        .fold(IntervalSet::empty(), |acc, sensor| acc.union(&sensor));
+    }
+
+    fn points_in_box(&self, min: Coord, max: Coord) -> impl Iterator<Item = Coord> {
+        self.v_ranges_in_box(min, max)
+            .flat_map(|(u, v_from, v_to)| {
+                (v_from..=v_to)
+                    .step_by(2)
+                    .map(move |v| ((u + v) / 2, (u - v) / 2))
+            })
+    }
+
+    fn count_in_box(&self, min: Coord, max: Coord) -> usize {
+        self.v_ranges_in_box(min, max)
+            .filter(|(_u, v_from, v_to)| v_from <= v_to)
+            .map(|(_u, v_from, v_to)| ((v_to - v_from) / 2 + 1) as usize)
+            .sum()
+    }
+}
+
+pub struct SensorField {
+    sensors: Vec<Sensor>,
+}
+
+impl SensorField {
+    pub fn new(sensors: Vec<Sensor>) -> SensorField {
+        SensorField { sensors }
+    }
+
+    pub fn parse(input: &str) -> SensorField {
+        SensorField::new(
+            input
+                .lines()
+                .filter(|line| !line.starts_with(HEADER_PREFIX))
+                .map(Sensor::parse)
+                .collect(),
+        )
+    }
+
+    pub fn sensors(&self) -> &[Sensor] {
+        &self.sensors
+    }
+
+    pub fn is_covered(&self, point: Coord) -> bool {
+        self.sensors.iter().any(|s| s.covers(point))
+    }
+
+    pub fn covering_sensors(&self, point: Coord) -> Vec<&Sensor> {
+        self.sensors.iter().filter(|s| s.covers(point)).collect()
+    }
+
+    pub fn row_coverage(&self, y: isize) -> IntervalSet<isize> {
+        self.sensors
+            .iter()
+            .flat_map(|s| s.blocked_interval(s.pos.0, (s.pos.1 - y).abs()))
+            .fold(IntervalSet::empty(), |acc, next| acc.union(&next))
+    }
 
-    blocked_intervals
-        .difference(&beacon_intervals)
+    pub fn column_coverage(&self, x: isize) -> IntervalSet<isize> {
+        self.sensors
+            .iter()
+            .flat_map(|s| s.blocked_interval(s.pos.1, (s.pos.0 - x).abs()))
+            .fold(IntervalSet::empty(), |acc, next| acc.union(&next))
+    }
+
+    pub fn beacons_in_row(&self, y: isize) -> IntervalSet<isize> {
+        self.sensors
+            .iter()
+            .filter(|s| s.beacon_pos.1 == y)
+            .map(|s| IntervalSet::singleton(s.beacon_pos.0))
+            .fold(IntervalSet::empty(), |acc, next| acc.union(&next))
+    }
+
+    fn uncovered_rects(&self, (min_x, min_y): Coord, (max_x, max_y): Coord) -> Vec<Rect> {
+        let bounds = Rect {
+            u: (min_x + min_y, max_x + max_y),
+            v: (min_x - max_y, max_x - min_y),
+        };
+        self.sensors
+            .iter()
+            .map(Rect::covering)
+            .fold(vec![bounds], |uncovered, covering| {
+                uncovered
+                    .iter()
+                    .flat_map(|rect| rect.subtract(&covering))
+                    .collect()
+            })
+    }
+
+    // Every point of the box, bounds included, that no sensor covers.
+    pub fn uncovered_points(&self, min: Coord, max: Coord) -> Vec<Coord> {
+        let mut points: Vec<Coord> = self
+            .uncovered_rects(min, max)
+            .iter()
+            .flat_map(|rect| rect.points_in_box(min, max))
+            .collect();
+        points.sort_unstable();
+        points
+    }
+
+    pub fn covered_area(&self, min: Coord, max: Coord) -> usize {
+        if min.0 > max.0 || min.1 > max.1 {
+            return 0;
+        }
+        let area = ((max.0 - min.0 + 1) * (max.1 - min.1 + 1)) as usize;
+        let uncovered: usize = self
+            .uncovered_rects(min, max)
+            .iter()
+            .map(|rect| rect.count_in_box(min, max))
+            .sum();
+        area - uncovered
+    }
+}
+
+#[aoc(day15, part1)]
+pub fn part1(input: &str) -> usize {
+    let params = Params::parse(input);
+    let field = SensorField::parse(input);
+    field
+        .row_coverage(params.row)
+        .difference(&field.beacons_in_row(params.row))
         .iter()
         .map(|interval| interval.size())
         .sum()
 }
 
-#[aoc(day15, part1)]
-pub fn part1(input: &str) -> usize {
-    part1_impl(input, 2000000)
+#[aoc(day15, part2)]
+pub fn part2(input: &str) -> isize {
+    let params = Params::parse(input);
+    let field = SensorField::parse(input);
+    match field
+        .uncovered_points((0, 0), (params.limit, params.limit))
+        .first()
+    {
+        Some((x, y)) => x * params.frequency + y,
+        None => panic!("No solution"),
+    }
 }
 
-fn part2_impl(input: &str, limit: isize) -> isize {
-    let sensors: Vec<Sensor> = input.lines().map(Sensor::parse).collect();
-    for y in 0..=limit {
-        let blocked = blocked_intervals(&sensors, y);
-        let allowed = IntervalSet::new(0, limit).difference(&blocked);
+#[aoc(day15, part2, scan)]
+pub fn part2_scan(input: &str) -> isize {
+    let params = Params::parse(input);
+    let field = SensorField::parse(input);
+    for y in 0..=params.limit {
+        let blocked = field.row_coverage(y);
+        let allowed = IntervalSet::new(0, params.limit).difference(&blocked);
         if allowed.interval_count() == 1 {
             let allowed_interval = allowed.iter().next().unwrap();
             if allowed_interval.size() == 1 {
                 let x = allowed_interval.lower();
-                return x * 4_000_000 + y;
+                return x * params.frequency + y;
             }
         }
     }
     panic!("No solution")
 }
 
-#[aoc(day15, part2)]
-pub fn part2(input: &str) -> isize {
-    part2_impl(input, 4_000_000)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -118,12 +351,99 @@ mod tests {
     #[test]
     fn test_example_part1() {
         let example = include_str!("examples/day15.txt");
-        assert_eq!(part1_impl(example, 10), 26);
+        assert_eq!(part1(example), 26);
     }
 
     #[test]
     fn test_example_part2() {
         let example = include_str!("examples/day15.txt");
-        assert_eq!(part2_impl(example, 20), 56000011);
+        assert_eq!(part2(example), 56000011);
+    }
+
+    #[test]
+    fn test_example_part2_scan() {
+        let example = include_str!("examples/day15.txt");
+        assert_eq!(part2_scan(example), 56000011);
+    }
+
+    #[test]
+    fn test_params() {
+        let example = include_str!("examples/day15.txt");
+        assert_eq!(
+            Params::parse(example),
+            Params {
+                row: 10,
+                limit: 20,
+                frequency: 4_000_000,
+            }
+        );
+        let sensors = example.lines().skip(1).collect::<Vec<_>>().join("\n");
+        assert_eq!(Params::parse(&sensors), Params::default());
+        let header = "# frequency=10, limit=20\n";
+        assert_eq!(part2(&(header.to_owned() + &sensors)), 14 * 10 + 11);
+    }
+
+    fn brute_force_uncovered(field: &SensorField, min: Coord, max: Coord) -> Vec<Coord> {
+        (min.0..=max.0)
+            .flat_map(|x| (min.1..=max.1).map(move |y| (x, y)))
+            .filter(|p| {
+                field
+                    .sensors()
+                    .iter()
+                    .all(|s| (s.pos.0 - p.0).abs() + (s.pos.1 - p.1).abs() > s.beacon_distance())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_uncovered_points_brute_force() {
+        let field = SensorField::parse(include_str!("examples/day15.txt"));
+        for (min, max) in [((0, 0), (20, 20)), ((-7, -3), (31, 26)), ((5, 8), (5, 8))] {
+            assert_eq!(
+                field.uncovered_points(min, max),
+                brute_force_uncovered(&field, min, max)
+            );
+        }
+        assert_eq!(field.uncovered_points((0, 0), (20, 20)), vec![(14, 11)]);
+    }
+
+    #[test]
+    fn test_point_queries() {
+        let field = SensorField::parse(include_str!("examples/day15.txt"));
+        assert!(field.is_covered((8, 7)));
+        assert!(field.is_covered((2, 10)));
+        assert!(!field.is_covered((14, 11)));
+        let covering: Vec<Coord> = field
+            .covering_sensors((10, 16))
+            .iter()
+            .map(|s| s.pos)
+            .collect();
+        assert_eq!(covering, vec![(9, 16), (12, 14), (10, 20), (14, 17)]);
+        assert!(field.covering_sensors((14, 11)).is_empty());
+    }
+
+    #[test]
+    fn test_covered_area() {
+        let field = SensorField::parse(include_str!("examples/day15.txt"));
+        for (min, max) in [((0, 0), (20, 20)), ((-7, -3), (31, 26)), ((3, 4), (9, 12))] {
+            let area = ((max.0 - min.0 + 1) * (max.1 - min.1 + 1)) as usize;
+            let uncovered = brute_force_uncovered(&field, min, max).len();
+            assert_eq!(field.covered_area(min, max), area - uncovered);
+        }
+        assert_eq!(field.covered_area((0, 0), (20, 20)), 21 * 21 - 1);
+        assert_eq!(field.covered_area((1, 1), (0, 0)), 0);
+    }
+
+    #[test]
+    fn test_row_and_column_coverage() {
+        let field = SensorField::parse(include_str!("examples/day15.txt"));
+        assert_eq!(field.row_coverage(10), IntervalSet::new(-2, 24));
+        for x in -5..30 {
+            let column = field.column_coverage(x);
+            for y in -15..40 {
+                assert_eq!(column.contains(&y), field.is_covered((x, y)));
+                assert_eq!(field.row_coverage(y).contains(&x), field.is_covered((x, y)));
+            }
+        }
     }
 }
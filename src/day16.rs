@@ -1,4 +1,7 @@
-use std::{cmp::Reverse, collections::HashMap};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, VecDeque},
+};
 
 use aoc_runner_derive::aoc;
 use regex::Regex;
@@ -19,11 +22,17 @@ struct Node {
 struct Input {
     // All nodes as parsed from input.
     nodes: Vec<Node>,
-    // Distance from each node to every other node.
+    // Node index by name.
+    index: HashMap<String, usize>,
+    // Indices of the neighbors of each node.
+    tunnels: Vec<Vec<usize>>,
+    // Node index of each valve the solvers care about: the candidates, then the start.
+    valves: Vec<usize>,
+    // Distance between every pair of valves, indexed like `valves`.
     dist: Vec<Vec<usize>>,
     // Index of the start node.
     start: usize,
-    // Pairs (index, pressure) for valves that have pressure >.
+    // Pairs (index into `valves`, pressure) for valves that have pressure >.
     candidates: Vec<(usize, usize)>,
 }
 
@@ -42,67 +51,139 @@ fn parse_node(input: &str) -> Node {
     }
 }
 
-fn parse_input(input: &str) -> Input {
+const START: &str = "AA";
+
+// Distance from the node to every other node, through the tunnels.
+fn tunnel_distances(tunnels: &[Vec<usize>], from: usize) -> Vec<usize> {
+    let mut dist = vec![usize::MAX / 2; tunnels.len()];
+    let mut queue = VecDeque::from([from]);
+    dist[from] = 0;
+    while let Some(u) = queue.pop_front() {
+        for &v in &tunnels[u] {
+            if dist[v] > dist[u] + 1 {
+                dist[v] = dist[u] + 1;
+                queue.push_back(v);
+            }
+        }
+    }
+    dist
+}
+
+fn parse_input(input: &str, start: &str) -> Input {
     let nodes: Vec<Node> = input.lines().map(parse_node).collect();
     // Map each node name to index, where index is the index of the input line.
-    let map: HashMap<&str, usize> = nodes
+    let index: HashMap<String, usize> = nodes
         .iter()
         .enumerate()
-        .map(|(i, s)| (s.name.as_str(), i))
+        .map(|(i, s)| (s.name.clone(), i))
         .collect();
-    let n = nodes.len();
-    // Start node is "AA".
-    let start = nodes
+    let start = *index
+        .get(start)
+        .unwrap_or_else(|| panic!("Unknown valve: {}", start));
+    let tunnels: Vec<Vec<usize>> = nodes
         .iter()
-        .enumerate()
-        .find(|(_i, n)| n.name == "AA")
-        .unwrap()
-        .0;
-    // We can consider jumping only between valves with pressure to speed up backtracking.
-    // Lets calculuate distance between each nodes with Floyd–Warshall algo.
-    let mut dist = vec![vec![usize::MAX / 2; n]; n];
-    for (u, node) in nodes.iter().enumerate() {
-        dist[u][u] = 0;
-        for n in &node.neighbors {
-            dist[u][*map.get(n.as_str()).unwrap()] = 1;
-        }
-    }
-    for k in 0..n {
-        for i in 0..n {
-            for j in 0..n {
-                let d = dist[i][k] + dist[k][j];
-                if d < dist[i][j] {
-                    dist[i][j] = d;
-                }
-            }
-        }
-    }
+        .map(|node| node.neighbors.iter().map(|n| index[n]).collect())
+        .collect();
     // Take only nodes with pressure > 0 as candidates for backtracking.
-    let candidates: Vec<(usize, usize)> = nodes
+    let mut valves: Vec<usize> = nodes
         .iter()
         .enumerate()
         .filter(|(_i, n)| n.pressure > 0)
-        .map(|(i, n)| (i, n.pressure))
+        .map(|(i, _n)| i)
+        .collect();
+    let candidates = valves
+        .iter()
+        .enumerate()
+        .map(|(j, u)| (j, nodes[*u].pressure))
+        .collect();
+    valves.push(start);
+    // We can consider jumping only between valves with pressure to speed up backtracking,
+    // so we only need distances from those and the start: one BFS per valve.
+    let dist = valves
+        .iter()
+        .map(|u| {
+            let from_u = tunnel_distances(&tunnels, *u);
+            valves.iter().map(|v| from_u[*v]).collect()
+        })
         .collect();
     Input {
         nodes,
+        index,
+        tunnels,
+        valves,
         dist,
         start,
         candidates,
     }
 }
 
+impl Input {
+    // Index of the start in `valves`.
+    fn start_valve(&self) -> usize {
+        self.valves.len() - 1
+    }
+
+    fn valve(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    // Shortest walk through the tunnels, both ends included.
+    fn tunnel_path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        let mut parent = vec![usize::MAX; self.nodes.len()];
+        let mut queue = VecDeque::from([from]);
+        parent[from] = from;
+        while let Some(u) = queue.pop_front() {
+            for &v in &self.tunnels[u] {
+                if parent[v] == usize::MAX {
+                    parent[v] = u;
+                    queue.push_back(v);
+                }
+            }
+        }
+        if parent[to] == usize::MAX {
+            return None;
+        }
+        let mut path = vec![to];
+        while *path.last().unwrap() != from {
+            path.push(parent[*path.last().unwrap()]);
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+// A valve opening as (agent, node, budget left once the valve is open).
+type Opening = (usize, usize, usize);
+
+// Openings along the current search path, and the best path found so far with its pressure.
+#[derive(Default)]
+struct Trail {
+    path: Vec<Opening>,
+    best: (usize, Vec<Opening>),
+}
+
+impl Trail {
+    fn record(&mut self, pressure: usize) {
+        if pressure > self.best.0 {
+            self.best = (pressure, self.path.clone());
+        }
+    }
+}
+
 // Simple backtracking over all candidates order until we are out of budget.
 // Can be potentially improved by some pruning, but takes only ~11 ms for Part 1, so not worth it.
 // 'Greedy' (e.g. taking the best option on every step) doesn't work.
 // Likely there is some DP approach, but it is not apparent for me.
 fn backtrack1(
     (u, budget): (usize, usize),
+    agent: usize,
     pressure: usize,
     dist: &Vec<Vec<usize>>,
     nodes: &[(usize, usize)],
     visited: &mut [bool],
+    trail: &mut Trail,
 ) -> usize {
+    trail.record(pressure);
     if budget < 2 {
         return pressure;
     }
@@ -113,13 +194,17 @@ fn backtrack1(
         }
         visited[v] = true;
         let next_budget = budget - dist[u][v] - 1;
+        trail.path.push((agent, v, next_budget));
         let next_pressure = backtrack1(
             (v, next_budget),
+            agent,
             pressure + v_pressure * next_budget,
             dist,
             nodes,
             visited,
+            trail,
         );
+        trail.path.pop();
         if next_pressure > max_pressure {
             max_pressure = next_pressure;
         }
@@ -140,13 +225,15 @@ fn backtrack2(
     dist: &Vec<Vec<usize>>,
     nodes: &[(usize, usize)],
     visited: &mut [bool],
+    trail: &mut Trail,
 ) -> usize {
     if budget_1 < 2 {
-        return backtrack1((u_2, budget_2), pressure, dist, nodes, visited);
+        return backtrack1((u_2, budget_2), 1, pressure, dist, nodes, visited, trail);
     }
     if budget_2 < 2 {
-        return backtrack1((u_1, budget_1), pressure, dist, nodes, visited);
+        return backtrack1((u_1, budget_1), 0, pressure, dist, nodes, visited, trail);
     }
+    trail.record(pressure);
     let mut max_pressure = pressure;
     for &(v, v_pressure) in nodes {
         if !visited[v] {
@@ -157,6 +244,7 @@ fn backtrack2(
                     || budget_1 - dist[u_1][v] >= budget_2 - dist[u_2][v])
             {
                 let next_budget_1 = budget_1 - dist[u_1][v] - 1;
+                trail.path.push((0, v, next_budget_1));
                 max_pressure = max_pressure.max(backtrack2(
                     (v, next_budget_1),
                     (u_2, budget_2),
@@ -164,9 +252,12 @@ fn backtrack2(
                     dist,
                     nodes,
                     visited,
+                    trail,
                 ));
+                trail.path.pop();
             } else if budget_2 > dist[u_2][v] + 1 {
                 let next_budget_2 = budget_2 - dist[u_2][v] - 1;
+                trail.path.push((1, v, next_budget_2));
                 max_pressure = max_pressure.max(backtrack2(
                     (u_1, budget_1),
                     (v, next_budget_2),
@@ -174,7 +265,9 @@ fn backtrack2(
                     dist,
                     nodes,
                     visited,
+                    trail,
                 ));
+                trail.path.pop();
             }
 
             visited[v] = false;
@@ -183,23 +276,206 @@ fn backtrack2(
     max_pressure
 }
 
+// Best single-agent plan for every subset of candidates, indexed by a bit mask over candidate
+// indices: the pressure released by opening exactly those valves and the order to open them.
+struct SubsetPlans {
+    pressure: Vec<usize>,
+    order: Vec<Vec<usize>>,
+}
+
+fn subset_plans(
+    (u, budget): (usize, usize),
+    (mask, pressure): (usize, usize),
+    input: &Input,
+    path: &mut Vec<usize>,
+    plans: &mut SubsetPlans,
+) {
+    if pressure > plans.pressure[mask] {
+        plans.pressure[mask] = pressure;
+        plans.order[mask] = path.clone();
+    }
+    for (j, &(v, v_pressure)) in input.candidates.iter().enumerate() {
+        if mask & (1 << j) != 0 || budget <= input.dist[u][v] + 1 {
+            continue;
+        }
+        let next_budget = budget - input.dist[u][v] - 1;
+        path.push(j);
+        subset_plans(
+            (v, next_budget),
+            (mask | (1 << j), pressure + v_pressure * next_budget),
+            input,
+            path,
+            plans,
+        );
+        path.pop();
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub agent: usize,
+    pub valve: String,
+    // Minute during which the valve is opened, counting from 1.
+    pub minute: usize,
+    // Pressure the valve releases over the remaining minutes.
+    pub pressure: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    // Total pressure released.
+    pub pressure: usize,
+    // Valve openings of all agents, ordered by minute.
+    pub steps: Vec<Step>,
+}
+
+impl Schedule {
+    fn from_openings(input: &Input, budget: usize, openings: &[Opening]) -> Schedule {
+        let mut steps: Vec<Step> = openings
+            .iter()
+            .map(|&(agent, v, left)| Step {
+                agent,
+                valve: input.nodes[input.valves[v]].name.clone(),
+                minute: budget - left,
+                pressure: input.nodes[input.valves[v]].pressure * left,
+            })
+            .collect();
+        steps.sort_by_key(|step| (step.minute, step.agent));
+        Schedule {
+            pressure: steps.iter().map(|step| step.pressure).sum(),
+            steps,
+        }
+    }
+
+    // Valves opened by the agent, in order.
+    pub fn valves(&self, agent: usize) -> Vec<&str> {
+        self.steps
+            .iter()
+            .filter(|step| step.agent == agent)
+            .map(|step| step.valve.as_str())
+            .collect()
+    }
+}
+
+// Agents open disjoint sets of valves, so the best joint plan is the best split of candidates
+// into one set per agent, each handled optimally by a single agent.
+fn best_plan(input: &Input, budget: usize, agents: usize) -> Schedule {
+    let n = input.candidates.len();
+    let full = (1 << n) - 1;
+    let mut plans = SubsetPlans {
+        pressure: vec![0; 1 << n],
+        order: vec![vec![]; 1 << n],
+    };
+    subset_plans(
+        (input.start_valve(), budget),
+        (0, 0),
+        input,
+        &mut vec![],
+        &mut plans,
+    );
+
+    // Relax to the best plan over all subsets of each mask, remembering which subset it is.
+    let mut relaxed: Vec<(usize, usize)> = (0..=full).map(|m| (plans.pressure[m], m)).collect();
+    for j in 0..n {
+        for mask in 0..=full {
+            if mask & (1 << j) != 0 && relaxed[mask ^ (1 << j)].0 > relaxed[mask].0 {
+                relaxed[mask] = relaxed[mask ^ (1 << j)];
+            }
+        }
+    }
+
+    // best[k][mask] is the best pressure for k + 1 agents sharing the valves of mask, and
+    // split[k][mask] the share of the last agent.
+    let mut best = vec![relaxed.iter().map(|(p, _)| *p).collect::<Vec<_>>()];
+    let mut split = vec![(0..=full).collect::<Vec<_>>()];
+    for k in 1..agents {
+        let (mut next_best, mut next_split) = (vec![0; full + 1], vec![0; full + 1]);
+        for mask in 0..=full {
+            let mut sub = mask;
+            loop {
+                let pressure = relaxed[sub].0 + best[k - 1][mask ^ sub];
+                if pressure > next_best[mask] {
+                    next_best[mask] = pressure;
+                    next_split[mask] = sub;
+                }
+                if sub == 0 {
+                    break;
+                }
+                sub = (sub - 1) & mask;
+            }
+        }
+        best.push(next_best);
+        split.push(next_split);
+    }
+
+    let mut openings = vec![];
+    let mut mask = full;
+    for (agent, k) in (0..agents).rev().enumerate() {
+        let sub = split[k][mask];
+        let (mut u, mut left) = (input.start_valve(), budget);
+        for j in &plans.order[relaxed[sub].1] {
+            let v = input.candidates[*j].0;
+            left -= input.dist[u][v] + 1;
+            openings.push((agent, v, left));
+            u = v;
+        }
+        mask ^= sub;
+    }
+    Schedule::from_openings(input, budget, &openings)
+}
+
+pub fn best_schedule(input: &str, start: &str, budget: usize, agents: usize) -> Schedule {
+    best_plan(&parse_input(input, start), budget, agents)
+}
+
 #[aoc(day16, part1)]
 pub fn part1(input: &str) -> usize {
-    let input = parse_input(input);
-    let mut candidates = input.candidates;
-    candidates.sort_by_key(|(i, p)| (Reverse(*p), input.dist[0][*i]));
-    let mut visited = vec![false; input.nodes.len()];
+    backtrack1_schedule(&parse_input(input, START), 30).pressure
+}
 
-    backtrack1((input.start, 30), 0, &input.dist, &candidates, &mut visited)
+fn backtrack1_schedule(input: &Input, budget: usize) -> Schedule {
+    let mut candidates = input.candidates.clone();
+    candidates.sort_by_key(|(i, p)| (Reverse(*p), input.dist[input.start_valve()][*i]));
+    let mut visited = vec![false; input.valves.len()];
+    let mut trail = Trail::default();
+    backtrack1(
+        (input.start_valve(), budget),
+        0,
+        0,
+        &input.dist,
+        &candidates,
+        &mut visited,
+        &mut trail,
+    );
+    Schedule::from_openings(input, budget, &trail.best.1)
+}
+
+fn backtrack2_schedule(input: &Input, budget: usize) -> Schedule {
+    let mut candidates = input.candidates.clone();
+    candidates.sort_by_key(|(i, p)| (Reverse(*p), input.dist[input.start_valve()][*i]));
+    let mut visited = vec![false; input.valves.len()];
+    let mut trail = Trail::default();
+    backtrack2(
+        (input.start_valve(), budget),
+        (input.start_valve(), budget),
+        0,
+        &input.dist,
+        &candidates,
+        &mut visited,
+        &mut trail,
+    );
+    Schedule::from_openings(input, budget, &trail.best.1)
 }
 
 // Bottom-up DP for part 1. Apparently much slower than backtracking.
 #[aoc(day16, part1, dp)]
 pub fn part1_dp(input: &str) -> usize {
-    let input = parse_input(input);
+    dp_schedule(&parse_input(input, START), 30).pressure
+}
+
+fn dp_schedule(input: &Input, budget: usize) -> Schedule {
     let n = input.candidates.len();
     let lim: usize = 1 << n;
-    let budget = 30;
     let mut dp = vec![vec![vec![0_usize; n + 1]; lim]; budget + 1];
 
     let is_bit_set = |m, u| (m & (1_usize << u)) != 0;
@@ -212,7 +488,7 @@ pub fn part1_dp(input: &str) -> usize {
                 .iter()
                 .enumerate()
                 // Add start ('AA') as one of the candidates.
-                .chain(std::iter::once((n, &(input.start, 0_usize))))
+                .chain(std::iter::once((n, &(input.start_valve(), 0_usize))))
             {
                 let mut r = 0;
                 for (j, &(v, p)) in input.candidates.iter().enumerate() {
@@ -226,23 +502,90 @@ pub fn part1_dp(input: &str) -> usize {
         }
     }
 
-    dp[budget][0][n]
+    // Walk the table back from the start to recover which valve each optimum opened.
+    let mut openings = vec![];
+    let (mut b, mut m, mut i) = (budget, 0, n);
+    while dp[b][m][i] > 0 {
+        let u = input.candidates.get(i).map_or(input.start_valve(), |c| c.0);
+        let (j, next_b) = input
+            .candidates
+            .iter()
+            .enumerate()
+            .filter(|(j, &(v, _))| !is_bit_set(m, *j) && b > input.dist[u][v] + 1)
+            .map(|(j, &(v, p))| (j, b - input.dist[u][v] - 1, p))
+            .find(|&(j, next_b, p)| dp[next_b][set_bit(m, j)][j] + p * next_b == dp[b][m][i])
+            .map(|(j, next_b, _)| (j, next_b))
+            .unwrap();
+        openings.push((0, input.candidates[j].0, next_b));
+        (b, m, i) = (next_b, set_bit(m, j), j);
+    }
+    Schedule::from_openings(input, budget, &openings)
 }
 
 #[aoc(day16, part2)]
 pub fn part2(input: &str) -> usize {
-    let input = parse_input(input);
-    let mut candidates = input.candidates;
-    candidates.sort_by_key(|(i, p)| (Reverse(*p), input.dist[0][*i]));
-    let mut visited = vec![false; input.nodes.len()];
-    backtrack2(
-        (input.start, 26),
-        (input.start, 26),
-        0,
-        &input.dist,
-        &candidates,
-        &mut visited,
-    )
+    best_schedule(input, START, 26, 2).pressure
+}
+
+#[aoc(day16, part2, backtrack)]
+pub fn part2_backtrack(input: &str) -> usize {
+    backtrack2_schedule(&parse_input(input, START), 26).pressure
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    UnknownValve(String),
+    NoFlow(String),
+    OpenedTwice(String),
+    // The agent cannot walk from its previous valve in time to open this one.
+    Unreachable(Step),
+    WrongPressure(Step),
+    WrongTotal { expected: usize, actual: usize },
+}
+
+// Walks every agent through the tunnels from the start, checking that each step of the schedule
+// can be done in time and releases the claimed pressure.
+pub fn replay(
+    input: &str,
+    start: &str,
+    budget: usize,
+    schedule: &Schedule,
+) -> Result<(), ReplayError> {
+    let input = parse_input(input, start);
+    let mut opened = vec![false; input.nodes.len()];
+    let mut agents: HashMap<usize, (usize, usize)> = HashMap::new();
+    let mut total = 0;
+    for step in &schedule.steps {
+        let v = input
+            .valve(&step.valve)
+            .ok_or_else(|| ReplayError::UnknownValve(step.valve.clone()))?;
+        if input.nodes[v].pressure == 0 {
+            return Err(ReplayError::NoFlow(step.valve.clone()));
+        }
+        if opened[v] {
+            return Err(ReplayError::OpenedTwice(step.valve.clone()));
+        }
+        opened[v] = true;
+        let (u, time) = *agents.get(&step.agent).unwrap_or(&(input.start, 0));
+        let reachable = input
+            .tunnel_path(u, v)
+            .is_some_and(|path| step.minute >= time + path.len());
+        if !reachable || step.minute > budget {
+            return Err(ReplayError::Unreachable(step.clone()));
+        }
+        if step.pressure != input.nodes[v].pressure * (budget - step.minute) {
+            return Err(ReplayError::WrongPressure(step.clone()));
+        }
+        agents.insert(step.agent, (v, step.minute));
+        total += step.pressure;
+    }
+    if total != schedule.pressure {
+        return Err(ReplayError::WrongTotal {
+            expected: schedule.pressure,
+            actual: total,
+        });
+    }
+    Ok(())
 }
 
 // Can't figure out the right bottom-up DP for the part2. Also very slow (2x faster than backtracking)
@@ -250,7 +593,7 @@ pub fn part2(input: &str) -> usize {
 //#[aoc(day16, part2, dp)]
 #[allow(unused)]
 pub fn part2_dp(input: &str) -> usize {
-    let input = parse_input(input);
+    let input = parse_input(input, START);
     let n = input.candidates.len();
     let lim: usize = 1 << n;
     let budget = 26;
@@ -267,7 +610,7 @@ pub fn part2_dp(input: &str) -> usize {
                     .iter()
                     .enumerate()
                     // Add start ('AA') as one of the candidates.
-                    .chain(std::iter::once((n, &(input.start, 0_usize))))
+                    .chain(std::iter::once((n, &(input.start_valve(), 0_usize))))
                 {
                     let mut r = 0;
                     for (j, &(v, p)) in input.candidates.iter().enumerate() {
@@ -290,9 +633,125 @@ pub fn part2_dp(input: &str) -> usize {
     dp[budget][budget][0][n]
 }
 
+const ROUTE_COLORS: [&str; 4] = ["red", "blue", "darkgreen", "orange"];
+
+fn dot_nodes(input: &Input, nodes: &[usize], schedule: Option<&Schedule>, dot: &mut String) {
+    for &u in nodes {
+        let node = &input.nodes[u];
+        let mut label = format!("{}\\n{}", node.name, node.pressure);
+        let mut attrs = vec![];
+        if let Some(step) = schedule.and_then(|s| s.steps.iter().find(|s| s.valve == node.name)) {
+            label += &format!(" @{}", step.minute);
+            attrs.push(format!(
+                "color={}",
+                ROUTE_COLORS[step.agent % ROUTE_COLORS.len()]
+            ));
+            attrs.push("penwidth=2".to_string());
+        }
+        if u == input.start {
+            attrs.push("shape=doublecircle, style=filled, fillcolor=lightblue".to_string());
+        } else if node.pressure == 0 {
+            attrs.push("style=dashed".to_string());
+        }
+        attrs.insert(0, format!("label=\"{}\"", label));
+        dot.push_str(&format!("    {} [{}];\n", node.name, attrs.join(", ")));
+    }
+}
+
+// Node hops of each agent's route, from the start through every valve it opens.
+fn route_hops(input: &Input, schedule: &Schedule) -> Vec<(usize, usize, usize)> {
+    let mut position: HashMap<usize, usize> = HashMap::new();
+    let mut hops = vec![];
+    for step in &schedule.steps {
+        // A hand-written schedule may name a valve that is not in the input: the agent stays put.
+        let Some(v) = input.valve(&step.valve) else {
+            continue;
+        };
+        let u = position.insert(step.agent, v).unwrap_or(input.start);
+        hops.push((step.agent, u, v));
+    }
+    hops
+}
+
+fn dot_edge(input: &Input, (u, v): (usize, usize), attrs: &[String], dot: &mut String) {
+    let (a, b) = (&input.nodes[u].name, &input.nodes[v].name);
+    if attrs.is_empty() {
+        dot.push_str(&format!("    {} -- {};\n", a, b));
+    } else {
+        dot.push_str(&format!("    {} -- {} [{}];\n", a, b, attrs.join(", ")));
+    }
+}
+
+fn route_attrs(agent: usize) -> Vec<String> {
+    vec![
+        format!("color={}", ROUTE_COLORS[agent % ROUTE_COLORS.len()]),
+        "penwidth=3".to_string(),
+    ]
+}
+
+// The raw tunnel graph in Graphviz DOT, with each agent's walk highlighted if a schedule is given.
+pub fn tunnels_dot(input: &str, start: &str, schedule: Option<&Schedule>) -> String {
+    let input = parse_input(input, start);
+    let mut routed: HashMap<(usize, usize), usize> = HashMap::new();
+    for (agent, u, v) in schedule.map(|s| route_hops(&input, s)).unwrap_or_default() {
+        // A hand-written schedule may hop between disconnected valves: draw nothing for it.
+        let Some(path) = input.tunnel_path(u, v) else {
+            continue;
+        };
+        for (a, b) in path.iter().zip(path.iter().skip(1)) {
+            routed.entry((*a.min(b), *a.max(b))).or_insert(agent);
+        }
+    }
+    let mut dot = String::from("graph tunnels {\n");
+    let all: Vec<usize> = (0..input.nodes.len()).collect();
+    dot_nodes(&input, &all, schedule, &mut dot);
+    for (u, neighbors) in input.tunnels.iter().enumerate() {
+        for &v in neighbors {
+            if u < v || !input.tunnels[v].contains(&u) {
+                let attrs = routed
+                    .get(&(u.min(v), u.max(v)))
+                    .map(|agent| route_attrs(*agent))
+                    .unwrap_or_default();
+                dot_edge(&input, (u, v), &attrs, &mut dot);
+            }
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+// The compressed graph over the start and valves with flow, edges labelled with walking distance.
+pub fn distances_dot(input: &str, start: &str, schedule: Option<&Schedule>) -> String {
+    let input = parse_input(input, start);
+    let mut routed: HashMap<(usize, usize), usize> = HashMap::new();
+    for (agent, u, v) in schedule.map(|s| route_hops(&input, s)).unwrap_or_default() {
+        routed.entry((u.min(v), u.max(v))).or_insert(agent);
+    }
+    // Start first, skipping its copy among the candidates if it has flow itself.
+    let valves: Vec<usize> = std::iter::once(input.start_valve())
+        .chain((0..input.start_valve()).filter(|i| input.valves[*i] != input.start))
+        .collect();
+    let nodes: Vec<usize> = valves.iter().map(|i| input.valves[*i]).collect();
+    let mut dot = String::from("graph distances {\n");
+    dot_nodes(&input, &nodes, schedule, &mut dot);
+    for (i, &a) in valves.iter().enumerate() {
+        for &b in &valves[i + 1..] {
+            let (u, v) = (input.valves[a], input.valves[b]);
+            let mut attrs = vec![format!("label={}", input.dist[a][b])];
+            if let Some(agent) = routed.get(&(u.min(v), u.max(v))) {
+                attrs.extend(route_attrs(*agent));
+            }
+            dot_edge(&input, (u, v), &attrs, &mut dot);
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_rng::Rng;
 
     #[test]
     fn test_example_part1() {
@@ -312,6 +771,386 @@ mod tests {
         assert_eq!(part2(example), 1707);
     }
 
+    #[test]
+    fn test_example_part2_backtrack() {
+        let example = include_str!("examples/day16.txt");
+        assert_eq!(part2_backtrack(example), 1707);
+    }
+
+    #[test]
+    fn test_solver_schedules() {
+        let example = include_str!("examples/day16.txt");
+        let input = parse_input(example, START);
+        let expected = vec![
+            ("DD", 2, 560),
+            ("BB", 5, 325),
+            ("JJ", 9, 441),
+            ("HH", 17, 286),
+            ("EE", 21, 27),
+            ("CC", 24, 12),
+        ];
+        for schedule in [backtrack1_schedule(&input, 30), dp_schedule(&input, 30)] {
+            assert_eq!(schedule.pressure, 1651);
+            let steps: Vec<(&str, usize, usize)> = schedule
+                .steps
+                .iter()
+                .map(|step| (step.valve.as_str(), step.minute, step.pressure))
+                .collect();
+            assert_eq!(steps, expected);
+            assert_eq!(replay(example, START, 30, &schedule), Ok(()));
+        }
+        for schedule in [backtrack2_schedule(&input, 26), best_plan(&input, 26, 2)] {
+            assert_eq!(schedule.pressure, 1707);
+            assert_eq!(replay(example, START, 26, &schedule), Ok(()));
+        }
+        for agents in 1..=3 {
+            let schedule = best_plan(&input, 20, agents);
+            assert_eq!(replay(example, START, 20, &schedule), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_replay_errors() {
+        let example = include_str!("examples/day16.txt");
+        let schedule = best_schedule(example, START, 30, 1);
+        let broken = |edit: &dyn Fn(&mut Schedule)| {
+            let mut schedule = schedule.clone();
+            edit(&mut schedule);
+            replay(example, START, 30, &schedule).unwrap_err()
+        };
+        assert_eq!(
+            broken(&|s| s.steps[0].valve = "ZZ".into()),
+            ReplayError::UnknownValve("ZZ".into())
+        );
+        assert_eq!(
+            broken(&|s| s.steps[0].valve = "AA".into()),
+            ReplayError::NoFlow("AA".into())
+        );
+        assert_eq!(
+            broken(&|s| s.steps[1].valve = "DD".into()),
+            ReplayError::OpenedTwice("DD".into())
+        );
+        assert!(matches!(
+            broken(&|s| s.steps[0].minute = 1),
+            ReplayError::Unreachable(_)
+        ));
+        assert!(matches!(
+            broken(&|s| s.steps[0].pressure += 1),
+            ReplayError::WrongPressure(_)
+        ));
+        assert_eq!(
+            broken(&|s| s.pressure += 1),
+            ReplayError::WrongTotal {
+                expected: 1652,
+                actual: 1651
+            }
+        );
+    }
+
+    #[test]
+    fn test_tunnels_dot() {
+        let example = include_str!("examples/day16.txt");
+        let dot = tunnels_dot(example, START, None);
+        assert!(dot.starts_with("graph tunnels {\n"));
+        assert!(dot.contains(
+            "    AA [label=\"AA\\n0\", shape=doublecircle, style=filled, fillcolor=lightblue];\n"
+        ));
+        assert!(dot.contains("    BB [label=\"BB\\n13\"];\n"));
+        assert!(dot.contains("    FF [label=\"FF\\n0\", style=dashed];\n"));
+        assert_eq!(dot.matches(" -- ").count(), 10);
+
+        let schedule = best_schedule(example, START, 30, 1);
+        let dot = tunnels_dot(example, START, Some(&schedule));
+        assert!(dot.contains("    DD [label=\"DD\\n20 @2\", color=red, penwidth=2];\n"));
+        assert!(dot.contains("    AA -- DD [color=red, penwidth=3];\n"));
+        assert!(dot.contains("    GG -- HH [color=red, penwidth=3];\n"));
+        // The best single-agent walk uses every tunnel of the example.
+        assert_eq!(dot.matches("penwidth=3").count(), 10);
+
+        let islands = "Valve AA has flow rate=0; tunnels lead to valves BB\n\
+                       Valve BB has flow rate=3; tunnels lead to valves AA\n\
+                       Valve CC has flow rate=5; tunnels lead to valves DD\n\
+                       Valve DD has flow rate=0; tunnels lead to valves CC";
+        let schedule = Schedule {
+            pressure: 0,
+            steps: ["BB", "CC"]
+                .iter()
+                .enumerate()
+                .map(|(i, valve)| Step {
+                    agent: 0,
+                    valve: valve.to_string(),
+                    minute: 2 * i + 2,
+                    pressure: 0,
+                })
+                .collect(),
+        };
+        let dot = tunnels_dot(islands, START, Some(&schedule));
+        assert!(dot.contains("    AA -- BB [color=red, penwidth=3];\n"));
+        assert!(dot.contains("    CC -- DD;\n"));
+    }
+
+    #[test]
+    fn test_distances_dot() {
+        let example = include_str!("examples/day16.txt");
+        let dot = distances_dot(example, START, None);
+        assert!(dot.starts_with("graph distances {\n"));
+        assert!(!dot.contains("FF"));
+        // Start and six valves with flow, fully connected.
+        assert_eq!(dot.matches(" -- ").count(), 21);
+        assert!(dot.contains("    AA -- HH [label=5];\n"));
+
+        let schedule = best_schedule(example, START, 26, 2);
+        let dot = distances_dot(example, START, Some(&schedule));
+        assert!(dot.contains("color=blue, penwidth=3"));
+        assert_eq!(dot.matches("penwidth=3").count(), 6);
+    }
+
+    #[test]
+    fn test_dot_skips_unknown_valves() {
+        let example = include_str!("examples/day16.txt");
+        let schedule = best_schedule(example, START, 30, 1);
+        let mut unknown = schedule.clone();
+        unknown.steps.insert(
+            1,
+            Step {
+                agent: 0,
+                valve: "ZZ".to_string(),
+                minute: 3,
+                pressure: 0,
+            },
+        );
+        assert_eq!(
+            tunnels_dot(example, START, Some(&unknown)),
+            tunnels_dot(example, START, Some(&schedule))
+        );
+        assert_eq!(
+            distances_dot(example, START, Some(&unknown)),
+            distances_dot(example, START, Some(&schedule))
+        );
+    }
+
+    // A connected graph of n valves, at most `flowing` of them with flow.
+    fn random_valves(rng: &mut Rng, n: usize, flowing: usize) -> String {
+        let letter = |i: usize| (b'A' + i as u8) as char;
+        let name = |i: usize| match i / 26 {
+            hi if hi < 26 => format!("{}{}", letter(hi), letter(i % 26)),
+            hi => format!("{}{}{}", letter(hi / 26), letter(hi % 26), letter(i % 26)),
+        };
+        let mut edges = vec![vec![]; n];
+        for v in 1..n {
+            let u = rng.below(v);
+            edges[u].push(v);
+            edges[v].push(u);
+        }
+        for _ in 0..rng.below(n) {
+            let (u, v) = (rng.below(n), rng.below(n));
+            if u != v && !edges[u].contains(&v) {
+                edges[u].push(v);
+                edges[v].push(u);
+            }
+        }
+        let mut flows = 0;
+        (0..n)
+            .map(|u| {
+                let pressure = if u == 0 || rng.below(3) == 0 || flows == flowing {
+                    0
+                } else {
+                    flows += 1;
+                    1 + rng.below(25)
+                };
+                let neighbors = edges[u].iter().map(|v| name(*v)).collect::<Vec<_>>();
+                format!(
+                    "Valve {} has flow rate={}; tunnels lead to valves {}",
+                    name(u),
+                    pressure,
+                    neighbors.join(", ")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // Memoized search over every joint move of both agents on the raw tunnel graph.
+    fn exhaustive2(input: &Input, budget: usize) -> usize {
+        fn go(
+            input: &Input,
+            (time, u_1, u_2, open): (usize, usize, usize, u64),
+            memo: &mut HashMap<(usize, usize, usize, u64), usize>,
+        ) -> usize {
+            if time == 0 {
+                return 0;
+            }
+            if let Some(&r) = memo.get(&(time, u_1, u_2, open)) {
+                return r;
+            }
+            let moves = |u: usize| {
+                let node = &input.nodes[u];
+                let mut moves: Vec<(usize, Option<usize>)> = node
+                    .neighbors
+                    .iter()
+                    .map(|name| {
+                        (
+                            input.nodes.iter().position(|n| &n.name == name).unwrap(),
+                            None,
+                        )
+                    })
+                    .collect();
+                if node.pressure > 0 && open & (1 << u) == 0 {
+                    moves.push((u, Some(u)));
+                }
+                moves
+            };
+            let mut best = 0;
+            for (v_1, open_1) in moves(u_1) {
+                for (v_2, open_2) in moves(u_2) {
+                    if open_1.is_some() && open_1 == open_2 {
+                        continue;
+                    }
+                    let mut next_open = open;
+                    let mut released = 0;
+                    for v in open_1.iter().chain(open_2.iter()) {
+                        next_open |= 1 << v;
+                        released += input.nodes[*v].pressure * (time - 1);
+                    }
+                    best = best.max(released + go(input, (time - 1, v_1, v_2, next_open), memo));
+                }
+            }
+            memo.insert((time, u_1, u_2, open), best);
+            best
+        }
+        go(
+            input,
+            (budget, input.start, input.start, 0),
+            &mut HashMap::new(),
+        )
+    }
+
+    // Distances between all nodes, as parsing computed them before switching to BFS.
+    fn floyd_warshall(input: &Input) -> Vec<Vec<usize>> {
+        let n = input.nodes.len();
+        let mut dist = vec![vec![usize::MAX / 2; n]; n];
+        for (u, neighbors) in input.tunnels.iter().enumerate() {
+            dist[u][u] = 0;
+            for &v in neighbors {
+                dist[u][v] = 1;
+            }
+        }
+        for k in 0..n {
+            for i in 0..n {
+                for j in 0..n {
+                    dist[i][j] = dist[i][j].min(dist[i][k] + dist[k][j]);
+                }
+            }
+        }
+        dist
+    }
+
+    #[test]
+    fn test_distances_match_floyd_warshall() {
+        let mut rng = Rng::new(0x1600_0035);
+        for _ in 0..20 {
+            let n = 2 + rng.below(20);
+            let input = parse_input(&random_valves(&mut rng, n, n), START);
+            let dist = floyd_warshall(&input);
+            for (a, &u) in input.valves.iter().enumerate() {
+                for (b, &v) in input.valves.iter().enumerate() {
+                    assert_eq!(input.dist[a][b], dist[u][v]);
+                }
+            }
+            assert_eq!(input.valves[input.start_valve()], input.start);
+        }
+    }
+
+    // Run with `cargo test --release -- --ignored bench_distances --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_distances() {
+        let mut rng = Rng::new(0x1600_0035);
+        for n in [500, 2000] {
+            let valves = random_valves(&mut rng, n, 15);
+            let now = std::time::Instant::now();
+            let input = parse_input(&valves, START);
+            let bfs = now.elapsed();
+            let now = std::time::Instant::now();
+            let dist = floyd_warshall(&input);
+            let fw = now.elapsed();
+            println!("{n} nodes: BFS {bfs:?}, Floyd-Warshall {fw:?}");
+            assert_eq!(input.candidates.len(), 15);
+            assert_eq!(
+                input.dist[0][input.start_valve()],
+                dist[input.valves[0]][input.start]
+            );
+        }
+    }
+
+    #[test]
+    fn test_part2_matches_exhaustive_search() {
+        let mut rng = Rng::new(0x2022_1216);
+        for _ in 0..30 {
+            let n = 2 + rng.below(7);
+            let budget = 2 + rng.below(9);
+            let input = parse_input(&random_valves(&mut rng, n, n), START);
+            assert_eq!(
+                best_plan(&input, budget, 2).pressure,
+                exhaustive2(&input, budget)
+            );
+        }
+        // And part2 itself, at its fixed budget.
+        for _ in 0..10 {
+            let n = 2 + rng.below(7);
+            let valves = random_valves(&mut rng, n, n);
+            assert_eq!(
+                part2(&valves),
+                exhaustive2(&parse_input(&valves, START), 26)
+            );
+        }
+    }
+
+    #[test]
+    fn test_best_schedule() {
+        let example = include_str!("examples/day16.txt");
+        let schedule = best_schedule(example, "AA", 30, 1);
+        assert_eq!(schedule.pressure, 1651);
+        assert_eq!(schedule.valves(0), vec!["DD", "BB", "JJ", "HH", "EE", "CC"]);
+        assert_eq!(best_schedule(example, "AA", 26, 0).pressure, 0);
+        let schedule = best_schedule(example, "AA", 26, 2);
+        assert_eq!(schedule.pressure, 1707);
+        let mut opened = [schedule.valves(0), schedule.valves(1)].concat();
+        opened.sort();
+        assert_eq!(opened, vec!["BB", "CC", "DD", "EE", "HH", "JJ"]);
+    }
+
+    #[test]
+    fn test_more_agents_match_partition_search() {
+        let example = include_str!("examples/day16.txt");
+        let input = parse_input(example, "AA");
+        let n = input.candidates.len();
+        // Assign every candidate to one of three agents (or none) and solve each agent alone.
+        let mut expected = 0;
+        for assignment in 0..4_usize.pow(n as u32) {
+            let mut pressure = 0;
+            for agent in 1..=3 {
+                let nodes: Vec<(usize, usize)> = (0..n)
+                    .filter(|j| (assignment / 4_usize.pow(*j as u32)) % 4 == agent)
+                    .map(|j| input.candidates[j])
+                    .collect();
+                let mut visited = vec![false; input.valves.len()];
+                pressure += backtrack1(
+                    (input.start_valve(), 10),
+                    0,
+                    0,
+                    &input.dist,
+                    &nodes,
+                    &mut visited,
+                    &mut Trail::default(),
+                );
+            }
+            expected = expected.max(pressure);
+        }
+        assert_eq!(best_schedule(example, "AA", 10, 3).pressure, expected);
+        assert!(best_schedule(example, "JJ", 10, 3).pressure > 0);
+    }
+
     /*
     #[test]
     fn test_example_part2_dp() {
@@ -1,185 +1,602 @@
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
 
 use aoc_runner_derive::aoc;
-use itertools::Itertools;
 
 type T = i32;
 type Point = (T, T);
 
-const Y_LIM: T = 7;
-const DEBUG: bool = false;
+// Bit mask of a chamber row, bit y standing for column y.
+type Row = u32;
+
+const MAX_WIDTH: T = Row::BITS as T;
+
+const ROCKS: &str = "\
+####
+
+.#.
+###
+.#.
+
+..#
+..#
+###
+
+#
+#
+#
+#
+
+##
+##";
 
 #[derive(Debug)]
-struct Figure {
+pub struct Figure {
     points: Vec<Point>,
+    // Bit mask of each row from the bottom.
+    rows: Vec<Row>,
+    width: T,
 }
 
 fn parse_figure(f: &[&str]) -> Figure {
     let mut points = Vec::new();
     for (x, line) in f.iter().rev().enumerate() {
         for (y, &b) in line.as_bytes().iter().enumerate() {
-            if b == b'#' {
-                points.push((x as T, y as T))
+            match b {
+                b'#' => points.push((x as T, y as T)),
+                b'.' => {}
+                _ => panic!("Wrong figure: {:?}", f),
             }
         }
     }
-    Figure { points }
+    let width = points.iter().map(|&(_x, y)| y + 1).max().unwrap_or(0);
+    if points.is_empty() || width > MAX_WIDTH {
+        panic!("Wrong figure: {:?}", f);
+    }
+    let mut rows = vec![0; f.len()];
+    for &(x, y) in &points {
+        rows[x as usize] |= 1 << y;
+    }
+    Figure {
+        points,
+        rows,
+        width,
+    }
+}
+
+// Rock shapes drawn with '#' and '.', top row first, separated by blank lines.
+pub fn parse_figures(input: &str) -> Vec<Figure> {
+    input
+        .split("\n\n")
+        .map(|f| f.lines().map(str::trim_end).collect::<Vec<_>>())
+        .filter(|f| !f.is_empty())
+        .map(|f| parse_figure(&f))
+        .collect()
 }
 
-fn get_figures() -> Vec<Figure> {
-    vec![
-        parse_figure(&["####"]),
-        parse_figure(&[".#.", "###", ".#."]),
-        parse_figure(&["..#", "..#", "###"]),
-        parse_figure(&["#", "#", "#", "#"]),
-        parse_figure(&["##", "##"]),
-    ]
+// Rock shapes from a definition file in the format of `parse_figures`.
+pub fn load_figures(path: &Path) -> io::Result<Vec<Figure>> {
+    Ok(parse_figures(&fs::read_to_string(path)?))
 }
 
+pub struct Config {
+    pub figures: Vec<Figure>,
+    // Chamber width, up to 32 columns.
+    pub width: T,
+    // Empty rows between the top of the tower and the bottom of a new rock.
+    pub spawn_gap: T,
+    // Empty columns between the left wall and a new rock.
+    pub spawn_column: T,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            figures: parse_figures(ROCKS),
+            width: 7,
+            spawn_gap: 3,
+            spawn_column: 2,
+        }
+    }
+}
+
+// Rows kept before the first attempt to trim the ones no rock can reach any more.
+const TRIM_ROWS: usize = 256;
+
 struct State<'a> {
-    occupied: HashSet<Point>,
-    shift: Box<dyn Iterator<Item = u8> + 'a>,
+    config: &'a Config,
+    // Bit mask of each row, starting from the floor or the lowest row still reachable.
+    rows: Vec<Row>,
+    // Height of rows[0].
+    base: T,
+    // Row count at which to look for unreachable rows again.
+    trim_at: usize,
+    jets: &'a [u8],
+    // Index of the next jet to apply.
+    jet: usize,
     max_height: T,
+    // Rocks locked so far.
+    rocks: usize,
+    // Times the unreachable rows were trimmed.
+    trims: usize,
+    recorder: Option<Recorder>,
 }
 
 impl<'a> State<'a> {
-    fn new(input: &'a str) -> Self {
+    fn new(input: &'a str, config: &'a Config) -> Self {
+        if !(1..=MAX_WIDTH).contains(&config.width) {
+            panic!("Wrong chamber width: {}", config.width);
+        }
+        let fits =
+            |f: &&Figure| config.spawn_column >= 0 && config.spawn_column + f.width <= config.width;
+        if let Some(f) = config.figures.iter().find(|f| !fits(f)) {
+            panic!(
+                "Figure does not fit the chamber at column {}: {:?}",
+                config.spawn_column, f.points
+            );
+        }
         State {
-            occupied: (0..7).map(|y| (0, y)).collect(),
-            shift: Box::new(input.trim_end().bytes().cycle()),
+            config,
+            rows: vec![Row::MAX >> (MAX_WIDTH - config.width)],
+            base: 0,
+            trim_at: TRIM_ROWS,
+            jets: input.trim_end().as_bytes(),
+            jet: 0,
             max_height: 0,
+            rocks: 0,
+            trims: 0,
+            recorder: None,
+        }
+    }
+
+    fn full_row(&self) -> Row {
+        Row::MAX >> (MAX_WIDTH - self.config.width)
+    }
+
+    fn row(&self, x: T) -> Row {
+        if x < self.base {
+            self.full_row()
+        } else {
+            self.rows
+                .get((x - self.base) as usize)
+                .copied()
+                .unwrap_or(0)
         }
     }
 
+    fn is_occupied(&self, (x, y): Point) -> bool {
+        self.row(x) & (1 << y) != 0
+    }
+
     fn is_valid(&self, (dx, dy): Point, figure: &Figure) -> bool {
-        figure.points.iter().all(|&(x, y)| {
-            let (x, y) = (x + dx, y + dy);
-            (0..Y_LIM).contains(&y) && y < Y_LIM && !self.occupied.contains(&(x, y))
-        })
+        dy >= 0
+            && dy + figure.width <= self.config.width
+            && figure
+                .rows
+                .iter()
+                .enumerate()
+                .all(|(x, &mask)| self.row(dx + x as T) & (mask << dy) == 0)
     }
 
     fn apply_shift(&mut self, dx: T, dy: &mut T, figure: &Figure) {
-        match self.shift.next() {
-            Some(b'<') => {
+        let shift = self.jets[self.jet];
+        self.jet = (self.jet + 1) % self.jets.len();
+        match shift {
+            b'<' => {
                 if self.is_valid((dx, *dy - 1), figure) {
                     *dy -= 1;
                 }
             }
-            Some(b'>') => {
+            b'>' => {
                 if self.is_valid((dx, *dy + 1), figure) {
                     *dy += 1;
                 }
             }
-            x => panic!("Unexpected shift: {x:?}"),
+            x => panic!("Unexpected shift: {:?}", x as char),
         }
     }
 
-    fn lock(&mut self, figure: &Figure) {
-        let (mut dx, mut dy) = (self.max_height + 4, 2);
+    fn lock(&mut self, figure: &Figure) -> io::Result<()> {
+        let (mut dx, mut dy) = (
+            self.max_height + self.config.spawn_gap + 1,
+            self.config.spawn_column,
+        );
+        let rock = self.rocks + 1;
         loop {
-            if DEBUG {
-                self.show_with_figure(
-                    figure
-                        .points
-                        .iter()
-                        .map(|&(x, y)| (x + dx, y + dy))
-                        .collect(),
-                );
+            if self.recorder.as_ref().is_some_and(|r| r.captures(rock)) {
+                let falling = figure
+                    .points
+                    .iter()
+                    .map(|&(x, y)| (x + dx, y + dy))
+                    .collect();
+                let frame = self.render(&falling);
+                let header = format!("Rock {}, jet {}", rock, self.jet + 1);
+                self.record(&header, &frame)?;
             }
             self.apply_shift(dx, &mut dy, figure);
-            let is_blocked = figure
-                .points
-                .iter()
-                .any(|&(x, y)| self.occupied.contains(&(x + dx - 1, y + dy)));
-            if is_blocked {
-                for &(x, y) in &figure.points {
-                    let (x, y) = (x + dx, y + dy);
-                    self.occupied.insert((x, y));
-                    self.max_height = self.max_height.max(x);
+            if self.is_valid((dx - 1, dy), figure) {
+                dx -= 1;
+            } else {
+                let top = (dx - self.base) as usize + figure.rows.len();
+                if self.rows.len() < top {
+                    self.rows.resize(top, 0);
                 }
+                for (x, &mask) in figure.rows.iter().enumerate() {
+                    self.rows[(dx - self.base) as usize + x] |= mask << dy;
+                }
+                self.max_height = self.max_height.max(dx + figure.rows.len() as T - 1);
                 break;
-            } else {
-                dx -= 1;
             }
         }
+        self.rocks = rock;
+        if self.recorder.as_ref().is_some_and(|r| r.captures(rock)) {
+            let frame = self.render(&HashSet::new());
+            self.record(&format!("Rock {} at rest", rock), &frame)?;
+        }
+        if self.rows.len() >= self.trim_at {
+            self.trim();
+        }
+        Ok(())
     }
 
-    fn show(&self) {
-        self.show_with_figure(HashSet::new());
+    fn record(&mut self, header: &str, frame: &str) -> io::Result<()> {
+        match &mut self.recorder {
+            Some(recorder) => recorder.record(header, frame),
+            None => Ok(()),
+        }
     }
 
-    fn show_with_figure(&self, f: HashSet<Point>) {
-        let lim = f.iter().map(|&(x, _y)| x).max().unwrap_or(self.max_height);
-
-        for x in (0..=lim).rev() {
-            let mut row = vec![b'.'; Y_LIM as usize];
-            for y in 0..Y_LIM {
-                if self.occupied.contains(&(x, y)) {
-                    row[y as usize] = b'#';
-                } else if f.contains(&(x, y)) {
-                    row[y as usize] = b'@';
+    // Drops the rows below the lowest empty cell that is still connected to the open space
+    // above, keeping one more row for rocks resting there to collide with.
+    fn trim(&mut self) {
+        let n = self.rows.len();
+        let width = self.config.width;
+        let mut reached: Vec<Row> = vec![0; n + 1];
+        reached[n] = self.full_row();
+        let mut queue: Vec<Point> = (0..width).map(|y| (n as T, y)).collect();
+        let mut lowest = n;
+        while let Some((x, y)) = queue.pop() {
+            lowest = lowest.min(x as usize);
+            let next = [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)];
+            for (x, y) in next {
+                if x < 0 || x as usize > n || !(0..width).contains(&y) {
+                    continue;
+                }
+                let (row, bit) = (x as usize, 1 << y);
+                if reached[row] & bit == 0 && (row == n || self.rows[row] & bit == 0) {
+                    reached[row] |= bit;
+                    queue.push((x, y));
                 }
             }
-            println!("{}", std::str::from_utf8(&row).unwrap());
         }
+        let drop = lowest.saturating_sub(1);
+        self.rows.drain(..drop);
+        self.base += drop as T;
+        self.trim_at = TRIM_ROWS.max(2 * self.rows.len());
+        self.trims += 1;
+    }
+
+    // Right after a trim, the rows from the lowest one a falling rock can still touch up to the
+    // top. Together with the next rock and jet, they decide everything that happens from here on.
+    fn surface(&self) -> &[Row] {
+        &self.rows[..(self.max_height - self.base + 1) as usize]
+    }
+
+    // The chamber as drawn in the puzzle, with the falling rock marked '@'. Rows trimmed away
+    // are hidden below a '~' line.
+    fn render(&self, falling: &HashSet<Point>) -> String {
+        let lim = falling
+            .iter()
+            .map(|&(x, _y)| x)
+            .max()
+            .unwrap_or(self.max_height);
+        let mut frame = String::new();
+        for x in (self.base.max(1)..=lim).rev() {
+            frame.push('|');
+            for y in 0..self.config.width {
+                frame.push(if self.is_occupied((x, y)) {
+                    '#'
+                } else if falling.contains(&(x, y)) {
+                    '@'
+                } else {
+                    '.'
+                });
+            }
+            frame.push_str("|\n");
+        }
+        let (corner, floor) = if self.base == 0 {
+            ('+', '-')
+        } else {
+            ('|', '~')
+        };
+        frame.push(corner);
+        frame.extend(std::iter::repeat_n(floor, self.config.width as usize));
+        frame.push(corner);
+        frame.push('\n');
+        frame
     }
 }
 
-#[aoc(day17, part1)]
-pub fn part1(input: &str) -> i32 {
-    let figures = get_figures();
-    let mut state = State::new(input);
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Capture {
+    // Every fall step of the given rocks, counting from 1, and each of them at rest.
+    Rocks(HashSet<usize>),
+    // Every fall step of every rock.
+    EveryJet,
+}
+
+enum Sink {
+    Memory(Vec<String>),
+    Stderr,
+    Text(BufWriter<File>),
+    Directory(PathBuf),
+}
+
+pub struct Recorder {
+    capture: Capture,
+    sink: Sink,
+    frames: usize,
+}
 
-    for figure in figures.iter().cycle().take(2022) {
-        state.lock(figure);
-        if DEBUG {
-            state.show();
+impl Recorder {
+    pub fn in_memory(capture: Capture) -> Recorder {
+        Recorder {
+            capture,
+            sink: Sink::Memory(vec![]),
+            frames: 0,
         }
     }
 
-    state.max_height
+    // All frames in one text file, separated by blank lines.
+    pub fn to_text(capture: Capture, path: &Path) -> io::Result<Recorder> {
+        Ok(Recorder {
+            capture,
+            sink: Sink::Text(BufWriter::new(File::create(path)?)),
+            frames: 0,
+        })
+    }
+
+    // One text file per frame, numbered in order.
+    pub fn to_directory(capture: Capture, dir: &Path) -> io::Result<Recorder> {
+        fs::create_dir_all(dir)?;
+        Ok(Recorder {
+            capture,
+            sink: Sink::Directory(dir.to_path_buf()),
+            frames: 0,
+        })
+    }
+
+    // Frames printed to stderr as they are recorded.
+    pub fn to_stderr(capture: Capture) -> Recorder {
+        Recorder {
+            capture,
+            sink: Sink::Stderr,
+            frames: 0,
+        }
+    }
+
+    // Set up by the runner through `DAY17_RECORD`, either `jets` or comma separated rock
+    // numbers, and `DAY17_RECORD_TO`, a text file or, with a trailing '/', a directory.
+    // Without a destination, frames go to stderr.
+    pub fn from_env() -> io::Result<Option<Recorder>> {
+        let Ok(spec) = env::var("DAY17_RECORD") else {
+            return Ok(None);
+        };
+        let capture = match spec.trim() {
+            "jets" => Capture::EveryJet,
+            rocks => Capture::Rocks(
+                rocks
+                    .split(',')
+                    .map(|r| {
+                        r.trim()
+                            .parse()
+                            .unwrap_or_else(|_| panic!("Wrong rock number: {}", r))
+                    })
+                    .collect(),
+            ),
+        };
+        match env::var("DAY17_RECORD_TO") {
+            Ok(path) if path.ends_with('/') => {
+                Recorder::to_directory(capture, Path::new(&path)).map(Some)
+            }
+            Ok(path) => Recorder::to_text(capture, Path::new(&path)).map(Some),
+            Err(_) => Ok(Some(Recorder::to_stderr(capture))),
+        }
+    }
+
+    fn captures(&self, rock: usize) -> bool {
+        match &self.capture {
+            Capture::Rocks(rocks) => rocks.contains(&rock),
+            Capture::EveryJet => true,
+        }
+    }
+
+    fn record(&mut self, header: &str, frame: &str) -> io::Result<()> {
+        self.frames += 1;
+        match &mut self.sink {
+            Sink::Memory(frames) => frames.push(format!("== {} ==\n{}", header, frame)),
+            Sink::Stderr => write!(io::stderr().lock(), "== {} ==\n{}\n", header, frame)?,
+            Sink::Text(w) => write!(w, "== {} ==\n{}\n", header, frame)?,
+            Sink::Directory(dir) => fs::write(
+                dir.join(format!("frame_{:06}.txt", self.frames)),
+                format!("== {} ==\n{}", header, frame),
+            )?,
+        }
+        Ok(())
+    }
+
+    // Frames kept by an in-memory recorder.
+    pub fn frames(&self) -> &[String] {
+        match &self.sink {
+            Sink::Memory(frames) => frames,
+            _ => &[],
+        }
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        match &mut self.sink {
+            Sink::Text(w) => w.flush(),
+            _ => Ok(()),
+        }
+    }
 }
 
-fn detect_cycle(height: &[i32], limit: usize, cycle_threshod: usize) -> Option<(usize, usize)> {
-    for cycle_width in 4..=(limit / cycle_threshod) {
-        let mut iter = height
-            .iter()
-            .rev()
-            .step_by(cycle_width)
-            .tuple_windows()
-            .map(|(l, c)| l - c);
-        let diff = iter.next().unwrap();
-        if iter.take_while(|&d| d == diff).take(cycle_threshod).count() == cycle_threshod {
-            return Some((cycle_width, diff as usize));
+fn drop_rocks<'a>(
+    input: &'a str,
+    config: &'a Config,
+    rocks: usize,
+    recorder: Option<Recorder>,
+) -> io::Result<State<'a>> {
+    let mut state = State::new(input, config);
+    state.recorder = recorder;
+    for figure in config.figures.iter().cycle().take(rocks) {
+        state.lock(figure)?;
+    }
+    if let Some(recorder) = &mut state.recorder {
+        recorder.finish()?;
+    }
+    Ok(state)
+}
+
+#[aoc(day17, part1)]
+pub fn part1(input: &str) -> io::Result<i32> {
+    let recorder = Recorder::from_env()?;
+    Ok(drop_rocks(input, &Config::default(), 2022, recorder)?.max_height)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cycle {
+    // Rocks dropped before the cycle starts.
+    pub start: usize,
+    // Rocks dropped per cycle.
+    pub length: usize,
+    // Tower growth per cycle.
+    pub height_gain: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct NoCycle {
+    pub rocks: usize,
+}
+
+impl std::fmt::Display for NoCycle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "No cycle found within {} rocks", self.rocks)
+    }
+}
+
+impl std::error::Error for NoCycle {}
+
+#[derive(Debug)]
+pub enum TowerError {
+    NoCycle(NoCycle),
+    Record(io::Error),
+}
+
+impl From<NoCycle> for TowerError {
+    fn from(e: NoCycle) -> Self {
+        TowerError::NoCycle(e)
+    }
+}
+
+impl From<io::Error> for TowerError {
+    fn from(e: io::Error) -> Self {
+        TowerError::Record(e)
+    }
+}
+
+impl std::fmt::Display for TowerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TowerError::NoCycle(e) => e.fmt(f),
+            TowerError::Record(e) => write!(f, "Failed to record frame: {}", e),
         }
     }
-    None
 }
 
-#[aoc(day17, part2)]
-pub fn part2(input: &str) -> usize {
-    let figures = get_figures();
-    let mut state = State::new(input);
+impl std::error::Error for TowerError {}
 
-    const LIMIT: usize = 100_000;
-    const CYCLE_THRESHOLD: usize = 10;
+pub struct Tower {
+    pub cycle: Cycle,
+    // Tower height after each number of rocks, up to the first repeat of the cycle.
+    heights: Vec<usize>,
+    recorder: Option<Recorder>,
+}
 
-    let mut height: Vec<T> = Vec::with_capacity(LIMIT);
+impl Tower {
+    // Drops rocks until the chamber is in a state it has already been in, up to `limit` rocks.
+    // States are only compared right after a trim, when the surface is known without another
+    // flood fill.
+    pub fn simulate(
+        input: &str,
+        config: &Config,
+        limit: usize,
+        recorder: Option<Recorder>,
+    ) -> Result<Tower, TowerError> {
+        let figures = &config.figures;
+        let mut state = State::new(input, config);
+        state.recorder = recorder;
+        let mut seen: HashMap<(usize, usize, Vec<Row>), usize> = HashMap::new();
+        let mut heights = vec![0];
+        for rocks in 1..=limit {
+            let trims = state.trims;
+            state.lock(&figures[(rocks - 1) % figures.len()])?;
+            heights.push(state.max_height as usize);
+            if state.trims == trims {
+                continue;
+            }
+            let key = (rocks % figures.len(), state.jet, state.surface().to_vec());
+            if let Some(&start) = seen.get(&key) {
+                let cycle = Cycle {
+                    start,
+                    length: rocks - start,
+                    height_gain: heights[rocks] - heights[start],
+                };
+                let mut recorder = state.recorder.take();
+                if let Some(recorder) = &mut recorder {
+                    recorder.finish()?;
+                }
+                return Ok(Tower {
+                    cycle,
+                    heights,
+                    recorder,
+                });
+            }
+            seen.insert(key, rocks);
+        }
+        if let Some(recorder) = &mut state.recorder {
+            recorder.finish()?;
+        }
+        Err(NoCycle { rocks: limit }.into())
+    }
 
-    for figure in figures.iter().cycle().take(LIMIT) {
-        state.lock(figure);
-        height.push(state.max_height);
+    // The recorder passed to `simulate`, once every rock up to the cycle is recorded.
+    pub fn recorder(&self) -> Option<&Recorder> {
+        self.recorder.as_ref()
     }
 
-    if let Some((cycle_width, height_diff)) = detect_cycle(&height, LIMIT, CYCLE_THRESHOLD) {
-        const STEPS: usize = 1_000_000_000_000;
-        let rest = STEPS - LIMIT;
-        let skip = cycle_width - rest % cycle_width;
-        let result = *height.iter().rev().nth(skip).unwrap() as usize
-            + (1 + rest / cycle_width) * height_diff;
-        return result;
+    pub fn height(&self, rocks: usize) -> usize {
+        if rocks < self.heights.len() {
+            return self.heights[rocks];
+        }
+        let cycles = (rocks - self.cycle.start) / self.cycle.length;
+        let rest = (rocks - self.cycle.start) % self.cycle.length;
+        self.heights[self.cycle.start + rest] + cycles * self.cycle.height_gain
     }
-    panic!("No solution");
+}
+
+const CYCLE_SEARCH_LIMIT: usize = 100_000;
+
+#[aoc(day17, part2)]
+pub fn part2(input: &str) -> Result<usize, TowerError> {
+    let recorder = Recorder::from_env()?;
+    let tower = Tower::simulate(input, &Config::default(), CYCLE_SEARCH_LIMIT, recorder)?;
+    Ok(tower.height(1_000_000_000_000))
 }
 
 #[cfg(test)]
@@ -189,12 +606,180 @@ mod tests {
     #[test]
     fn test_example_part1() {
         let example = include_str!("examples/day17.txt");
-        assert_eq!(part1(example), 3068);
+        assert_eq!(part1(example).unwrap(), 3068);
     }
 
     #[test]
     fn test_example_part2() {
         let example = include_str!("examples/day17.txt");
-        assert_eq!(part2(example), 1514285714288);
+        assert_eq!(part2(example).unwrap(), 1514285714288);
+    }
+
+    #[test]
+    fn test_tower_heights() {
+        let example = include_str!("examples/day17.txt");
+        let config = Config::default();
+        let tower = Tower::simulate(example, &config, CYCLE_SEARCH_LIMIT, None).unwrap();
+        assert_eq!(tower.cycle.length % 5, 0);
+        let mut state = State::new(example, &config);
+        for (rocks, figure) in (1..=5000).zip(config.figures.iter().cycle()) {
+            state.lock(figure).unwrap();
+            assert_eq!(tower.height(rocks), state.max_height as usize);
+        }
+        assert_eq!(tower.height(2022), 3068);
+        assert_eq!(tower.height(0), 0);
+    }
+
+    // Run with `cargo test --release -- --ignored bench_part2 --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_part2() {
+        let example = include_str!("examples/day17.txt");
+        let now = std::time::Instant::now();
+        for _ in 0..100 {
+            assert_eq!(part2(example).unwrap(), 1514285714288);
+        }
+        println!("part2 on the example: {:?}", now.elapsed() / 100);
+    }
+
+    #[test]
+    fn test_no_cycle_within_limit() {
+        let example = include_str!("examples/day17.txt");
+        assert!(matches!(
+            Tower::simulate(example, &Config::default(), 10, None),
+            Err(TowerError::NoCycle(NoCycle { rocks: 10 }))
+        ));
+    }
+
+    #[test]
+    fn test_trim_keeps_heights() {
+        let example = include_str!("examples/day17.txt");
+        let config = Config::default();
+        let mut trimmed = State::new(example, &config);
+        let mut full = State::new(example, &config);
+        full.trim_at = usize::MAX;
+        for figure in config.figures.iter().cycle().take(5000) {
+            trimmed.lock(figure).unwrap();
+            full.lock(figure).unwrap();
+            assert_eq!(trimmed.max_height, full.max_height);
+        }
+        assert!(trimmed.base > 0);
+        assert!(trimmed.rows.len() < full.rows.len() / 10);
+    }
+
+    #[test]
+    fn test_parse_figures() {
+        let figures = parse_figures(ROCKS);
+        assert_eq!(figures.len(), 5);
+        assert_eq!(figures[1].rows, vec![0b010, 0b111, 0b010]);
+        assert_eq!(figures[2].rows, vec![0b111, 0b100, 0b100]);
+        assert_eq!(figures[3].width, 1);
+        assert_eq!(parse_figures("##\n#.\n\n\n###\n")[0].rows, vec![0b01, 0b11]);
+    }
+
+    #[test]
+    fn test_custom_config() {
+        let example = include_str!("examples/day17.txt");
+        // A single flat rock in a chamber it exactly fills stacks one row per rock.
+        let config = Config {
+            figures: parse_figures("#####"),
+            width: 5,
+            spawn_gap: 3,
+            spawn_column: 0,
+        };
+        let tower = Tower::simulate(example, &config, CYCLE_SEARCH_LIMIT, None).unwrap();
+        assert_eq!(tower.height(1_000_000), 1_000_000);
+
+        let wide = Config {
+            width: 8,
+            spawn_gap: 4,
+            spawn_column: 3,
+            ..Config::default()
+        };
+        let mut state = State::new(example, &wide);
+        for figure in wide.figures.iter().cycle().take(2022) {
+            state.lock(figure).unwrap();
+        }
+        let tower = Tower::simulate(example, &wide, CYCLE_SEARCH_LIMIT, None).unwrap();
+        assert_eq!(tower.height(2022), state.max_height as usize);
+        assert_ne!(tower.height(2022), 3068);
+    }
+
+    #[test]
+    #[should_panic(expected = "Figure does not fit the chamber at column 6")]
+    fn test_spawn_column_out_of_chamber() {
+        let example = include_str!("examples/day17.txt");
+        let config = Config {
+            spawn_column: 6,
+            ..Default::default()
+        };
+        let _ = Tower::simulate(example, &config, CYCLE_SEARCH_LIMIT, None);
+    }
+
+    #[test]
+    fn test_load_figures() {
+        let path = env::temp_dir().join(format!("day17-figures-{}.txt", std::process::id()));
+        fs::write(&path, ROCKS).unwrap();
+        let figures = load_figures(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(figures.len(), 5);
+        assert_eq!(figures[4].rows, vec![0b11, 0b11]);
+        assert!(load_figures(&path).is_err());
+    }
+
+    #[test]
+    fn test_record_rocks() {
+        let example = include_str!("examples/day17.txt");
+        let config = Config::default();
+        let recorder = Recorder::in_memory(Capture::Rocks(HashSet::from([1, 3])));
+        let state = drop_rocks(example, &config, 3, Some(recorder)).unwrap();
+        let frames = state.recorder.as_ref().unwrap().frames();
+        assert_eq!(
+            frames[0],
+            "== Rock 1, jet 1 ==\n|..@@@@.|\n|.......|\n|.......|\n|.......|\n+-------+\n"
+        );
+        assert_eq!(frames[4], "== Rock 1 at rest ==\n|..####.|\n+-------+\n");
+        assert!(frames[5].starts_with("== Rock 3, jet 9 ==\n"));
+        assert_eq!(
+            frames.last().unwrap(),
+            "== Rock 3 at rest ==\n\
+             |..#....|\n|..#....|\n|####...|\n|..###..|\n|...#...|\n|..####.|\n+-------+\n"
+        );
+    }
+
+    #[test]
+    fn test_record_every_jet_to_directory() {
+        let example = include_str!("examples/day17.txt");
+        let dir = env::temp_dir().join(format!("day17-frames-{}", std::process::id()));
+        let recorder = Recorder::to_directory(Capture::EveryJet, &dir).unwrap();
+        let config = Config::default();
+        let state = drop_rocks(example, &config, 2, Some(recorder)).unwrap();
+        // Rock 1 takes 4 jets and rock 2 takes 4 more, each followed by a frame at rest.
+        assert_eq!(state.jet, 8);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 10);
+        let last = fs::read_to_string(dir.join("frame_000010.txt")).unwrap();
+        assert!(last.starts_with("== Rock 2 at rest ==\n|...#...|\n"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_record_tower() {
+        let example = include_str!("examples/day17.txt");
+        let config = Config::default();
+        let recorder = Recorder::in_memory(Capture::Rocks(HashSet::from([2])));
+        let tower = Tower::simulate(example, &config, CYCLE_SEARCH_LIMIT, Some(recorder)).unwrap();
+        let frames = tower.recorder().unwrap().frames();
+        assert!(frames[0].starts_with("== Rock 2, jet 5 ==\n"));
+        assert!(frames.last().unwrap().starts_with("== Rock 2 at rest ==\n"));
+    }
+
+    #[test]
+    fn test_record_error() {
+        let example = include_str!("examples/day17.txt");
+        let dir = env::temp_dir().join(format!("day17-gone-{}", std::process::id()));
+        let recorder = Recorder::to_directory(Capture::EveryJet, &dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let config = Config::default();
+        assert!(drop_rocks(example, &config, 1, Some(recorder)).is_err());
     }
 }
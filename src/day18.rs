@@ -1,11 +1,14 @@
 use std::{
-    collections::{HashSet, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
+    fs::File,
+    io::{self, BufWriter, Write},
     ops::RangeInclusive,
+    path::Path,
 };
 
 use aoc_runner_derive::aoc;
 
-type Cube = (i32, i32, i32);
+pub type Cube = (i32, i32, i32);
 
 fn parse_input(input: &str) -> Vec<Cube> {
     input
@@ -110,6 +113,137 @@ impl Limits {
     fn is_within_limits(&self, (x, y, z): &Cube) -> bool {
         self.x_range().contains(x) && self.y_range().contains(y) && self.z_range().contains(z)
     }
+
+    fn padded(&self) -> Self {
+        Self {
+            min_x: self.min_x - 1,
+            max_x: self.max_x + 1,
+            min_y: self.min_y - 1,
+            max_y: self.max_y + 1,
+            min_z: self.min_z - 1,
+            max_z: self.max_z + 1,
+        }
+    }
+}
+
+// An empty droplet is bounded by the origin alone, so that the box around it is never empty.
+fn bounds(cubes: &[Cube]) -> Limits {
+    let mut limits = Limits::new();
+    for cube in cubes.iter().chain(cubes.is_empty().then_some(&(0, 0, 0))) {
+        limits.adjust(cube);
+    }
+    limits
+}
+
+// Dense set of cells within fixed limits.
+struct Voxels {
+    limits: Limits,
+    size_y: usize,
+    size_z: usize,
+    bits: Vec<u64>,
+}
+
+impl Voxels {
+    fn new(limits: Limits) -> Self {
+        let size = |range: RangeInclusive<i32>| (range.end() - range.start() + 1) as usize;
+        let (size_x, size_y, size_z) = (
+            size(limits.x_range()),
+            size(limits.y_range()),
+            size(limits.z_range()),
+        );
+        Self {
+            limits,
+            size_y,
+            size_z,
+            bits: vec![0; (size_x * size_y * size_z).div_ceil(64)],
+        }
+    }
+
+    fn index(&self, cube @ &(x, y, z): &Cube) -> Option<usize> {
+        if !self.limits.is_within_limits(cube) {
+            return None;
+        }
+        let x = (x - self.limits.min_x) as usize;
+        let y = (y - self.limits.min_y) as usize;
+        let z = (z - self.limits.min_z) as usize;
+        Some((x * self.size_y + y) * self.size_z + z)
+    }
+
+    fn contains(&self, cube: &Cube) -> bool {
+        self.index(cube)
+            .is_some_and(|i| self.bits[i / 64] & (1 << (i % 64)) != 0)
+    }
+
+    // Returns whether the cell was not in the set yet.
+    fn insert(&mut self, cube: &Cube) -> bool {
+        let i = self.index(cube).expect("Cell out of limits");
+        let mask = 1 << (i % 64);
+        let fresh = self.bits[i / 64] & mask == 0;
+        self.bits[i / 64] |= mask;
+        fresh
+    }
+}
+
+struct Fill {
+    lava: Voxels,
+    // Air connected to the outside of the bounding box, padded by one so that it wraps the
+    // droplet.
+    air: Voxels,
+    // Lava faces touched by that air.
+    area: usize,
+    // Cells of each air pocket enclosed by the lava.
+    pockets: Vec<Vec<Cube>>,
+}
+
+// Splits the air in the padded bounding box into connected components. The corner of the box
+// comes first and is always air, so the first component is the outside.
+fn flood_fill(cubes: &[Cube]) -> Fill {
+    let padded = bounds(cubes).padded();
+    let mut lava = Voxels::new(padded.padded());
+    for cube in cubes {
+        lava.insert(cube);
+    }
+    let mut seen = Voxels::new(bounds(cubes).padded());
+    let mut air = Voxels::new(bounds(cubes).padded());
+    let mut area = 0;
+    let mut pockets = vec![];
+    for x in padded.x_range() {
+        for y in padded.y_range() {
+            for z in padded.z_range() {
+                let from = (x, y, z);
+                if lava.contains(&from) || !seen.insert(&from) {
+                    continue;
+                }
+                let mut cells = vec![from];
+                let mut faces = 0;
+                let mut i = 0;
+                while i < cells.len() {
+                    for next in neighbors(&cells[i]) {
+                        if lava.contains(&next) {
+                            faces += 1;
+                        } else if padded.is_within_limits(&next) && seen.insert(&next) {
+                            cells.push(next);
+                        }
+                    }
+                    i += 1;
+                }
+                if cells[0] == (padded.min_x, padded.min_y, padded.min_z) {
+                    for cell in &cells {
+                        air.insert(cell);
+                    }
+                    area = faces;
+                } else {
+                    pockets.push(cells);
+                }
+            }
+        }
+    }
+    Fill {
+        lava,
+        air,
+        area,
+        pockets,
+    }
 }
 
 #[aoc(day18, part1)]
@@ -150,8 +284,8 @@ fn trapped_surface(from: &Cube, limits: &Limits, visited: &mut HashSet<Cube>) ->
     }
 }
 
-#[aoc(day18, part2)]
-pub fn part2(input: &str) -> usize {
+#[aoc(day18, part2, trapped)]
+pub fn part2_trapped(input: &str) -> usize {
     let cubes = parse_input(input);
     let mut limits = Limits::new();
     for cube in &cubes {
@@ -171,9 +305,419 @@ pub fn part2(input: &str) -> usize {
     result
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scan {
+    pub exterior_area: usize,
+    // Volume of each interior air pocket.
+    pub pockets: Vec<usize>,
+}
+
+pub fn scan(input: &str) -> Scan {
+    let fill = flood_fill(&parse_input(input));
+    Scan {
+        exterior_area: fill.area,
+        pockets: fill.pockets.iter().map(Vec::len).collect(),
+    }
+}
+
+#[aoc(day18, part2)]
+pub fn part2(input: &str) -> usize {
+    flood_fill(&parse_input(input)).area
+}
+
+// A droplet that can be edited one cube at a time. Air inside the bounding box of the lava
+// is either connected to the outside or kept in `pockets`, while everything beyond the box is
+// outside air.
+pub struct Droplet {
+    lava: HashSet<Cube>,
+    pockets: HashSet<Cube>,
+    // Only grows, so it covers the lava even after removals.
+    limits: Limits,
+    total: usize,
+    exterior: usize,
+    // Cells visited while checking whether an insert sealed off some air.
+    #[cfg(test)]
+    searched: usize,
+}
+
+impl Droplet {
+    pub fn new() -> Self {
+        Self {
+            lava: HashSet::new(),
+            pockets: HashSet::new(),
+            limits: Limits::new(),
+            total: 0,
+            exterior: 0,
+            #[cfg(test)]
+            searched: 0,
+        }
+    }
+
+    pub fn parse(input: &str) -> Self {
+        let cubes = parse_input(input);
+        if cubes.is_empty() {
+            return Self::new();
+        }
+        let fill = flood_fill(&cubes);
+        let limits = bounds(&cubes);
+        let pockets = fill.pockets.into_iter().flatten().collect();
+        let surface = OuterSurface::from_iter(cubes.iter());
+        Self {
+            lava: surface.occupied,
+            pockets,
+            limits,
+            total: surface.total,
+            exterior: fill.area,
+            #[cfg(test)]
+            searched: 0,
+        }
+    }
+
+    pub fn contains(&self, cube: &Cube) -> bool {
+        self.lava.contains(cube)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lava.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lava.is_empty()
+    }
+
+    pub fn total_area(&self) -> usize {
+        self.total
+    }
+
+    pub fn exterior_area(&self) -> usize {
+        self.exterior
+    }
+
+    pub fn pocket_volume(&self) -> usize {
+        self.pockets.len()
+    }
+
+    fn is_outside(&self, cube: &Cube) -> bool {
+        !self.lava.contains(cube) && !self.pockets.contains(cube)
+    }
+
+    fn lava_neighbors(&self, cube: &Cube) -> usize {
+        neighbors(cube)
+            .iter()
+            .filter(|c| self.lava.contains(c))
+            .count()
+    }
+
+    // Whether the outside air around the cube stays connected within the 3x3x3 block centered
+    // on it. If so, any outside path through the cube can go around it, and filling the cube
+    // cannot seal anything off.
+    fn is_locally_connected(&self, &(x, y, z): &Cube) -> bool {
+        let offset = |(dx, dy, dz): Cube| (x + dx, y + dy, z + dz);
+        let in_block = |(dx, dy, dz): &Cube| [dx, dy, dz].iter().all(|d| (-1..=1).contains(*d));
+        let air: Vec<Cube> = neighbors(&(0, 0, 0))
+            .into_iter()
+            .filter(|&d| self.is_outside(&offset(d)))
+            .collect();
+        let Some(&from) = air.first() else {
+            return true;
+        };
+        let mut visited = HashSet::from([from]);
+        let mut q = vec![from];
+        while let Some(d) = q.pop() {
+            for next in neighbors(&d) {
+                if in_block(&next)
+                    && next != (0, 0, 0)
+                    && self.is_outside(&offset(next))
+                    && visited.insert(next)
+                {
+                    q.push(next);
+                }
+            }
+        }
+        air.iter().all(|d| visited.contains(d))
+    }
+
+    // Returns whether the cube was not part of the droplet yet.
+    pub fn insert(&mut self, cube: Cube) -> bool {
+        if self.lava.contains(&cube) {
+            return false;
+        }
+        let blocked = self.lava_neighbors(&cube);
+        self.total = self.total + 6 - 2 * blocked;
+        self.limits.adjust(&cube);
+        let was_outside = !self.pockets.remove(&cube);
+        let sealing = was_outside && !self.is_locally_connected(&cube);
+        self.lava.insert(cube);
+        if !was_outside {
+            // Filling a pocket does not change the outside.
+            return true;
+        }
+        // All the air around the cube was outside, and its lava neighbors lose a face each.
+        self.exterior = self.exterior + 6 - 2 * blocked;
+        if !sealing {
+            return true;
+        }
+
+        // The cube split the air around it, and exactly one of the parts still reaches past
+        // the bounding box. Searches from each air neighbor take turns one cell at a time,
+        // merging when they meet, until at most one is left running, so sealing a pocket costs
+        // about its size per neighbor. When two sides of the same part only meet far away, the
+        // search can still walk most of the outside air in the bounding box.
+        let starts: Vec<Cube> = neighbors(&cube)
+            .into_iter()
+            .filter(|c| self.is_outside(c))
+            .collect();
+        let n = starts.len();
+        let mut owner: HashMap<Cube, usize> =
+            starts.iter().enumerate().map(|(i, &c)| (c, i)).collect();
+        // Each search points at the one it was merged into when they met.
+        let mut merged: Vec<usize> = (0..n).collect();
+        let find = |merged: &[usize], mut i: usize| {
+            while merged[i] != i {
+                i = merged[i];
+            }
+            i
+        };
+        let mut queues: Vec<VecDeque<Cube>> = starts.iter().map(|&c| VecDeque::from([c])).collect();
+        let mut cells: Vec<Vec<Cube>> = starts.iter().map(|&c| vec![c]).collect();
+        let mut escaped = vec![false; n];
+        loop {
+            let roots: Vec<usize> = (0..n).filter(|&i| merged[i] == i).collect();
+            let running: Vec<usize> = roots
+                .iter()
+                .copied()
+                .filter(|&i| !escaped[i] && !queues[i].is_empty())
+                .collect();
+            let outside_found = roots.iter().any(|&i| escaped[i]);
+            if running.len() <= usize::from(!outside_found) {
+                break;
+            }
+            for i in running {
+                if merged[i] != i || escaped[i] {
+                    continue;
+                }
+                let Some(c) = queues[i].pop_front() else {
+                    continue;
+                };
+                #[cfg(test)]
+                {
+                    self.searched += 1;
+                }
+                if !self.limits.is_within_limits(&c) {
+                    escaped[i] = true;
+                    continue;
+                }
+                for next in neighbors(&c) {
+                    if !self.is_outside(&next) {
+                        continue;
+                    }
+                    let Some(&other) = owner.get(&next) else {
+                        owner.insert(next, i);
+                        cells[i].push(next);
+                        queues[i].push_back(next);
+                        continue;
+                    };
+                    let j = find(&merged, other);
+                    if j != i {
+                        merged[j] = i;
+                        escaped[i] |= escaped[j];
+                        let queue = std::mem::take(&mut queues[j]);
+                        queues[i].extend(queue);
+                        let visited = std::mem::take(&mut cells[j]);
+                        cells[i].extend(visited);
+                    }
+                }
+            }
+        }
+        // Whatever finished without escaping is sealed off.
+        for i in 0..n {
+            if merged[i] != i || escaped[i] || !queues[i].is_empty() {
+                continue;
+            }
+            for c in &cells[i] {
+                self.exterior -= self.lava_neighbors(c);
+            }
+            self.pockets.extend(cells[i].iter().copied());
+        }
+        true
+    }
+
+    // Returns whether the cube was part of the droplet.
+    pub fn remove(&mut self, cube: &Cube) -> bool {
+        if !self.lava.remove(cube) {
+            return false;
+        }
+        let blocked = self.lava_neighbors(cube);
+        self.total = self.total + 2 * blocked - 6;
+        let outside = neighbors(cube)
+            .iter()
+            .filter(|c| self.is_outside(c))
+            .count();
+        if outside == 0 {
+            // The cell joins the pockets around it, or becomes one.
+            self.pockets.insert(*cube);
+            return true;
+        }
+        // The cube's own faces to the outside go away and its lava neighbors get exposed.
+        self.exterior = self.exterior + blocked - outside;
+        // Pockets next to it are now open.
+        let mut q: VecDeque<Cube> = neighbors(cube)
+            .into_iter()
+            .filter(|c| self.pockets.remove(c))
+            .collect();
+        self.exterior += q.iter().map(|c| self.lava_neighbors(c)).sum::<usize>();
+        while let Some(c) = q.pop_front() {
+            for next in neighbors(&c) {
+                if self.pockets.remove(&next) {
+                    self.exterior += self.lava_neighbors(&next);
+                    q.push_back(next);
+                }
+            }
+        }
+        true
+    }
+}
+
+impl Default for Droplet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeshFormat {
+    Obj,
+    Stl,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MeshOptions {
+    pub format: MeshFormat,
+    // Only the faces reachable from outside, as counted by part 2.
+    pub exterior_only: bool,
+}
+
+impl Default for MeshOptions {
+    fn default() -> Self {
+        MeshOptions {
+            format: MeshFormat::Obj,
+            exterior_only: false,
+        }
+    }
+}
+
+// A unit square on the surface of the droplet, as four corners ordered counterclockwise when
+// seen from outside.
+type Quad = [Cube; 4];
+
+// The face of `cube` turned towards `neighbors(cube)[side]`.
+fn face_quad(&(x, y, z): &Cube, side: usize) -> Quad {
+    let axis = side / 2;
+    let positive = side.is_multiple_of(2);
+    let origin = [x, y, z];
+    let corner = |du: i32, dv: i32| {
+        let mut p = origin;
+        if positive {
+            p[axis] += 1;
+        }
+        p[(axis + 1) % 3] += du;
+        p[(axis + 2) % 3] += dv;
+        (p[0], p[1], p[2])
+    };
+    let quad = [corner(0, 0), corner(1, 0), corner(1, 1), corner(0, 1)];
+    if positive {
+        quad
+    } else {
+        [quad[0], quad[3], quad[2], quad[1]]
+    }
+}
+
+fn surface_quads(cubes: &[Cube], exterior_only: bool) -> Vec<(Quad, usize)> {
+    let fill = flood_fill(cubes);
+    let mut sorted = cubes.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+    let mut quads = vec![];
+    for cube in &sorted {
+        for (side, next) in neighbors(cube).iter().enumerate() {
+            let visible = if exterior_only {
+                fill.air.contains(next)
+            } else {
+                !fill.lava.contains(next)
+            };
+            if visible {
+                quads.push((face_quad(cube, side), side));
+            }
+        }
+    }
+    quads
+}
+
+fn write_obj(quads: &[(Quad, usize)], w: &mut impl Write) -> io::Result<()> {
+    let mut vertices: HashMap<Cube, usize> = HashMap::new();
+    let mut faces = vec![];
+    for (quad, _side) in quads {
+        let mut face = [0; 4];
+        for (i, corner) in quad.iter().enumerate() {
+            let next = vertices.len() + 1;
+            face[i] = *vertices.entry(*corner).or_insert(next);
+        }
+        faces.push(face);
+    }
+    let mut ordered: Vec<(&Cube, &usize)> = vertices.iter().collect();
+    ordered.sort_unstable_by_key(|(_v, i)| **i);
+    for ((x, y, z), _i) in ordered {
+        writeln!(w, "v {} {} {}", x, y, z)?;
+    }
+    for [a, b, c, d] in faces {
+        writeln!(w, "f {} {} {} {}", a, b, c, d)?;
+    }
+    Ok(())
+}
+
+fn write_stl(quads: &[(Quad, usize)], w: &mut impl Write) -> io::Result<()> {
+    writeln!(w, "solid droplet")?;
+    for (quad, side) in quads {
+        let mut normal = [0; 3];
+        normal[side / 2] = if side.is_multiple_of(2) { 1 } else { -1 };
+        for triangle in [[quad[0], quad[1], quad[2]], [quad[0], quad[2], quad[3]]] {
+            writeln!(
+                w,
+                "  facet normal {} {} {}",
+                normal[0], normal[1], normal[2]
+            )?;
+            writeln!(w, "    outer loop")?;
+            for (x, y, z) in triangle {
+                writeln!(w, "      vertex {} {} {}", x, y, z)?;
+            }
+            writeln!(w, "    endloop")?;
+            writeln!(w, "  endfacet")?;
+        }
+    }
+    writeln!(w, "endsolid droplet")
+}
+
+fn write_mesh(input: &str, options: &MeshOptions, w: &mut impl Write) -> io::Result<usize> {
+    let quads = surface_quads(&parse_input(input), options.exterior_only);
+    match options.format {
+        MeshFormat::Obj => write_obj(&quads, w)?,
+        MeshFormat::Stl => write_stl(&quads, w)?,
+    }
+    Ok(quads.len())
+}
+
+// Writes the droplet surface and returns the number of unit faces in it.
+pub fn export_mesh(input: &str, options: &MeshOptions, file: &Path) -> io::Result<usize> {
+    let mut w = BufWriter::new(File::create(file)?);
+    let faces = write_mesh(input, options, &mut w)?;
+    w.flush()?;
+    Ok(faces)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_rng::Rng;
 
     #[test]
     fn test_example_part1() {
@@ -186,4 +730,250 @@ mod tests {
         let example = include_str!("examples/day18.txt");
         assert_eq!(part2(example), 58);
     }
+
+    #[test]
+    fn test_example_part2_trapped() {
+        let example = include_str!("examples/day18.txt");
+        assert_eq!(part2_trapped(example), 58);
+    }
+
+    fn hollow_box(from: i32, to: i32) -> Vec<Cube> {
+        let range = || from..=to;
+        let mut cubes = vec![];
+        for x in range() {
+            for y in range() {
+                for z in range() {
+                    if [x, y, z].iter().any(|&c| c == from || c == to) {
+                        cubes.push((x, y, z));
+                    }
+                }
+            }
+        }
+        cubes
+    }
+
+    fn to_input(cubes: &[Cube]) -> String {
+        cubes
+            .iter()
+            .map(|(x, y, z)| format!("{},{},{}\n", x, y, z))
+            .collect()
+    }
+
+    #[test]
+    fn test_scan_pockets() {
+        let example = include_str!("examples/day18.txt");
+        assert_eq!(
+            scan(example),
+            Scan {
+                exterior_area: 58,
+                pockets: vec![1]
+            }
+        );
+
+        // A wall across the 4x4x4 hole of the box leaves 4x4x2 and 4x4x1 pockets, and a
+        // 2x2x1 block takes 4 cells from the first.
+        let mut cubes = hollow_box(0, 5);
+        for x in 1..=4 {
+            for y in 1..=4 {
+                cubes.push((x, y, 3));
+            }
+        }
+        cubes.extend([(1, 1, 1), (1, 2, 1), (2, 1, 1), (2, 2, 1)]);
+        let scanned = scan(&to_input(&cubes));
+        assert_eq!(scanned.exterior_area, 6 * 36);
+        assert_eq!(scanned.pockets, vec![28, 16]);
+    }
+
+    #[test]
+    fn test_part2_matches_trapped() {
+        let mut rng = Rng::new(0x2022_1218);
+        let mut next = |n: usize| rng.below(n) as i32;
+        for _ in 0..20 {
+            let cubes: Vec<Cube> = (0..300).map(|_| (next(8), next(8), next(8))).collect();
+            let input = to_input(&cubes);
+            assert_eq!(part2(&input), part2_trapped(&input));
+            let scanned = scan(&input);
+            let pocket_faces = part1(&input) - scanned.exterior_area;
+            assert_eq!(scanned.pockets.is_empty(), pocket_faces == 0);
+        }
+    }
+
+    fn mesh(input: &str, format: MeshFormat, exterior_only: bool) -> (String, usize) {
+        let mut out = vec![];
+        let options = MeshOptions {
+            format,
+            exterior_only,
+        };
+        let faces = write_mesh(input, &options, &mut out).unwrap();
+        (String::from_utf8(out).unwrap(), faces)
+    }
+
+    #[test]
+    fn test_single_cube_mesh() {
+        let (obj, faces) = mesh("1,1,1", MeshFormat::Obj, false);
+        assert_eq!(faces, 6);
+        assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 8);
+        assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 6);
+
+        let (stl, _faces) = mesh("1,1,1", MeshFormat::Stl, false);
+        assert!(stl.starts_with("solid droplet\n"));
+        assert_eq!(stl.matches("facet normal").count(), 12);
+        assert_eq!(stl.matches("facet normal 0 0 -1").count(), 2);
+    }
+
+    #[test]
+    fn test_empty_droplet() {
+        assert_eq!(part2(""), 0);
+        assert_eq!(
+            scan(""),
+            Scan {
+                exterior_area: 0,
+                pockets: vec![]
+            }
+        );
+        let (obj, faces) = mesh("", MeshFormat::Obj, true);
+        assert_eq!((obj.as_str(), faces), ("", 0));
+    }
+
+    #[test]
+    fn test_example_mesh_faces() {
+        let example = include_str!("examples/day18.txt");
+        assert_eq!(mesh(example, MeshFormat::Obj, false).1, 64);
+        assert_eq!(mesh(example, MeshFormat::Stl, true).1, 58);
+    }
+
+    #[test]
+    fn test_mesh_is_closed() {
+        // Every edge of a closed, consistently oriented surface is walked once in each direction.
+        let example = include_str!("examples/day18.txt");
+        for exterior_only in [false, true] {
+            let mut edges: HashMap<(Cube, Cube), i32> = HashMap::new();
+            for (quad, _side) in surface_quads(&parse_input(example), exterior_only) {
+                for i in 0..4 {
+                    let (a, b) = (quad[i], quad[(i + 1) % 4]);
+                    *edges.entry((a.min(b), a.max(b))).or_default() += if a < b { 1 } else { -1 };
+                }
+            }
+            assert!(edges.values().all(|&n| n == 0));
+        }
+    }
+
+    #[test]
+    fn test_face_normals_point_outwards() {
+        for side in 0..6 {
+            let [a, b, c, _d] = face_quad(&(0, 0, 0), side);
+            let u = (b.0 - a.0, b.1 - a.1, b.2 - a.2);
+            let v = (c.0 - a.0, c.1 - a.1, c.2 - a.2);
+            let normal = (
+                u.1 * v.2 - u.2 * v.1,
+                u.2 * v.0 - u.0 * v.2,
+                u.0 * v.1 - u.1 * v.0,
+            );
+            assert_eq!(normal, neighbors(&(0, 0, 0))[side]);
+        }
+    }
+
+    #[test]
+    fn test_droplet_parse() {
+        let example = include_str!("examples/day18.txt");
+        let droplet = Droplet::parse(example);
+        assert_eq!(droplet.len(), 13);
+        assert_eq!(droplet.total_area(), 64);
+        assert_eq!(droplet.exterior_area(), 58);
+        assert_eq!(droplet.pocket_volume(), 1);
+    }
+
+    #[test]
+    fn test_droplet_seal_and_open() {
+        let example = include_str!("examples/day18.txt");
+        let mut droplet = Droplet::parse(example);
+        // Filling the pocket leaves only the exterior.
+        assert!(droplet.insert((2, 2, 5)));
+        assert!(!droplet.insert((2, 2, 5)));
+        assert_eq!(droplet.total_area(), 58);
+        assert_eq!(droplet.exterior_area(), 58);
+        assert_eq!(droplet.pocket_volume(), 0);
+
+        let mut droplet = Droplet::new();
+        for cube in hollow_box(0, 2) {
+            if cube != (1, 1, 0) {
+                droplet.insert(cube);
+            }
+        }
+        assert_eq!(droplet.pocket_volume(), 0);
+        assert_eq!(droplet.exterior_area(), droplet.total_area());
+        droplet.insert((1, 1, 0));
+        assert_eq!(droplet.pocket_volume(), 1);
+        assert_eq!(droplet.exterior_area(), 54);
+        assert_eq!(droplet.total_area(), 60);
+        assert!(droplet.remove(&(1, 1, 2)));
+        assert!(!droplet.remove(&(1, 1, 2)));
+        assert_eq!(droplet.pocket_volume(), 0);
+        assert_eq!(droplet.exterior_area(), droplet.total_area());
+    }
+
+    #[test]
+    fn test_droplet_insert_work() {
+        // Growing a solid block never seals anything, so no searches are needed.
+        let mut droplet = Droplet::new();
+        for x in 0..12 {
+            for y in 0..12 {
+                for z in 0..12 {
+                    droplet.insert((x, y, z));
+                }
+            }
+        }
+        assert_eq!(droplet.searched, 0);
+        assert_eq!(droplet.exterior_area(), 6 * 144);
+
+        // Closing a hollow box takes one search, which walks little more than the pocket.
+        let mut droplet = Droplet::new();
+        for cube in hollow_box(0, 11) {
+            droplet.insert(cube);
+        }
+        assert_eq!(droplet.pocket_volume(), 1000);
+        assert!(droplet.searched < 1100);
+
+        // A small pocket far from the edges of the bounding box costs about its own size.
+        let mut droplet = Droplet::new();
+        droplet.insert((-40, -40, -40));
+        droplet.insert((40, 40, 40));
+        for cube in hollow_box(0, 4) {
+            droplet.insert(cube);
+        }
+        assert_eq!(droplet.pocket_volume(), 27);
+        assert!(droplet.searched < 100);
+    }
+
+    #[test]
+    fn test_droplet_matches_scratch() {
+        let mut rng = Rng::new(0x9e37_79b9_7f4a_7c15);
+        let mut next = |n: usize| rng.below(n) as i32;
+        let mut droplet = Droplet::new();
+        let mut cubes: HashSet<Cube> = HashSet::new();
+        for step in 0..3000 {
+            let cube = (next(7), next(7), next(7));
+            // Mostly grow early on and erode later.
+            if (next(100) < 70) == (step < 1500) {
+                assert_eq!(droplet.insert(cube), cubes.insert(cube));
+            } else {
+                assert_eq!(droplet.remove(&cube), cubes.remove(&cube));
+            }
+            if step % 10 == 0 {
+                let input = to_input(&cubes.iter().copied().collect::<Vec<_>>());
+                let expected = if cubes.is_empty() {
+                    (0, 0)
+                } else {
+                    (part1(&input), part2(&input))
+                };
+                assert_eq!((droplet.total_area(), droplet.exterior_area()), expected);
+                let pockets = if cubes.is_empty() {
+                    0
+                } else {
+                    scan(&input).pockets.iter().sum()
+                };
+                assert_eq!(droplet.pocket_volume(), pockets);
+            }
+        }
+    }
 }
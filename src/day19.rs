@@ -1,181 +1,430 @@
+use std::{collections::HashMap, fmt};
+
 use aoc_runner_derive::aoc;
 use rayon::prelude::*;
 use regex::Regex;
 
 lazy_static! {
-    static ref RE: Regex = Regex::new(r"Blueprint \d+: Each ore robot costs (\d+) ore. Each clay robot costs (\d+) ore. Each obsidian robot costs (\d+) ore and (\d+) clay. Each geode robot costs (\d+) ore and (\d+) obsidian.").unwrap();
+    static ref RECIPE_RE: Regex = Regex::new(r"Each (\w+) robot costs ([^.]+)\.").unwrap();
 }
 
-#[derive(Debug, Clone, Copy, Default)]
-struct Blueprint {
-    ore: u16,
-    clay: u16,
-    obsidian: (u16, u16),
-    geode: (u16, u16),
+// Amount of each resource kind, in the blueprint's order.
+type Amounts = Vec<u16>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlueprintError {
+    NoResources,
+    // Recipes given, when there must be one per resource.
+    WrongRecipes(usize),
+    // Robot whose cost does not list every resource.
+    WrongCost(usize),
+}
+
+impl fmt::Display for BlueprintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlueprintError::NoResources => write!(f, "No resources"),
+            BlueprintError::WrongRecipes(n) => write!(f, "{n} recipes, expected one per resource"),
+            BlueprintError::WrongCost(robot) => {
+                write!(f, "Cost of robot {robot} does not list every resource")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BlueprintError {}
+
+// Robot `i` collects resource `i`, and the last resource is the one to maximize. The factory
+// starts with one robot of the first kind. There may be any number of resources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blueprint {
+    names: Vec<String>,
+    costs: Vec<Amounts>,
+    max_robots: Amounts,
 }
 
 impl Blueprint {
-    fn max_ore_robots(&self) -> u16 {
-        self.clay.max(self.obsidian.0).max(self.geode.0)
+    pub fn new(names: &[&str], costs: &[Vec<u16>]) -> Result<Blueprint, BlueprintError> {
+        let kinds = names.len();
+        if kinds == 0 {
+            return Err(BlueprintError::NoResources);
+        }
+        if costs.len() != kinds {
+            return Err(BlueprintError::WrongRecipes(costs.len()));
+        }
+
+        let costs: Vec<Amounts> = costs
+            .iter()
+            .enumerate()
+            .map(|(robot, cost)| {
+                if cost.len() != kinds {
+                    return Err(BlueprintError::WrongCost(robot));
+                }
+                Ok(cost.clone())
+            })
+            .collect::<Result<_, _>>()?;
+        // Only one robot is built per minute, so collecting more than the largest cost per
+        // minute never helps.
+        let mut max_robots = vec![0; kinds];
+        for cost in &costs {
+            for (max, &n) in max_robots.iter_mut().zip(cost) {
+                *max = (*max).max(n);
+            }
+        }
+        max_robots[kinds - 1] = u16::MAX;
+
+        Ok(Blueprint {
+            names: names.iter().map(|n| n.to_string()).collect(),
+            costs,
+            max_robots,
+        })
+    }
+
+    pub fn resources(&self) -> &[String] {
+        &self.names
+    }
+
+    pub fn cost(&self, robot: usize) -> &[u16] {
+        &self.costs[robot]
+    }
+
+    fn kinds(&self) -> usize {
+        self.names.len()
+    }
+
+    fn target(&self) -> usize {
+        self.kinds() - 1
     }
 }
 
-fn parse_blueprint(line: &str) -> Blueprint {
-    let captures: Vec<u16> = RE
-        .captures(line)
-        .unwrap()
+// Reads a blueprint in the puzzle's words, with any resources. They are ordered as their robots
+// are listed.
+pub fn parse_blueprint(line: &str) -> Blueprint {
+    let recipes: Vec<(&str, &str)> = RECIPE_RE
+        .captures_iter(line)
+        .map(|c| (c.get(1).unwrap().as_str(), c.get(2).unwrap().as_str()))
+        .collect();
+    let names: Vec<&str> = recipes.iter().map(|(name, _)| *name).collect();
+    let costs: Vec<Vec<u16>> = recipes
         .iter()
-        .skip(1)
-        .flat_map(|c| c.unwrap().as_str().parse())
+        .map(|(_, cost)| {
+            let mut amounts = vec![0; names.len()];
+            for part in cost.split(" and ") {
+                let (n, resource) = part
+                    .split_once(' ')
+                    .unwrap_or_else(|| panic!("Wrong cost: {}", part));
+                let i = names
+                    .iter()
+                    .position(|name| *name == resource)
+                    .unwrap_or_else(|| panic!("Unknown resource: {}", resource));
+                amounts[i] = n.parse().unwrap();
+            }
+            amounts
+        })
         .collect();
-    Blueprint {
-        ore: captures[0],
-        clay: captures[1],
-        obsidian: (captures[2], captures[3]),
-        geode: (captures[4], captures[5]),
-    }
+    Blueprint::new(&names, &costs).unwrap_or_else(|e| panic!("Wrong blueprint: {}", e))
 }
 
-#[derive(Default, Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 struct State {
-    ore_robots: u16,
-    clay_robots: u16,
-    obsidian_robots: u16,
-    geode_robots: u16,
-
-    ore: u16,
-    clay: u16,
-    obsidian: u16,
-    geode: u16,
+    robots: Amounts,
+    resources: Amounts,
 }
 
 impl State {
-    fn new() -> Self {
-        Self {
-            ore_robots: 1,
-            ..Default::default()
+    fn new(blueprint: &Blueprint) -> Self {
+        let mut robots = vec![0; blueprint.kinds()];
+        robots[0] = 1;
+        State {
+            robots,
+            resources: vec![0; blueprint.kinds()],
         }
     }
 
-    fn add_ore_robot(&self, blueprint: &Blueprint) -> Option<Self> {
-        if self.ore >= blueprint.ore && self.ore_robots < blueprint.max_ore_robots() {
-            let adjusted = self.tick();
-            Some(Self {
-                ore_robots: adjusted.ore_robots + 1,
-                ore: adjusted.ore - blueprint.ore,
-                ..adjusted
-            })
-        } else {
-            None
+    // Spends a minute building the robot, which must be affordable.
+    fn build(self, blueprint: &Blueprint, robot: usize) -> Self {
+        let mut next = self.tick(1);
+        for (have, n) in next.resources.iter_mut().zip(blueprint.cost(robot)) {
+            *have -= n;
         }
+        next.robots[robot] += 1;
+        next
     }
 
-    fn add_clay_robot(&self, blueprint: &Blueprint) -> Option<Self> {
-        if self.ore >= blueprint.clay && self.clay_robots < blueprint.obsidian.1 {
-            let adjusted = self.tick();
-            Some(Self {
-                clay_robots: adjusted.clay_robots + 1,
-                ore: adjusted.ore - blueprint.clay,
-                ..adjusted
-            })
-        } else {
-            None
+    fn tick(mut self, minutes: u16) -> Self {
+        for (have, robots) in self.resources.iter_mut().zip(&self.robots) {
+            *have += robots * minutes;
         }
+        self
     }
 
-    fn add_obsidian_robot(&self, blueprint: &Blueprint) -> Option<Self> {
-        let (ore, clay) = blueprint.obsidian;
-        if self.ore >= ore && self.clay >= clay && self.obsidian_robots < blueprint.geode.1 {
-            let adjusted = self.tick();
-            Some(Self {
-                obsidian_robots: adjusted.obsidian_robots + 1,
-                ore: adjusted.ore - ore,
-                clay: adjusted.clay - clay,
-                ..adjusted
-            })
-        } else {
-            None
+    // Minutes to wait until the cost is affordable, if the robots collect everything it needs.
+    fn wait_for(&self, cost: &[u16]) -> Option<u16> {
+        let mut wait = 0;
+        for (i, &n) in cost.iter().enumerate() {
+            if n > self.resources[i] {
+                if self.robots[i] == 0 {
+                    return None;
+                }
+                wait = wait.max((n - self.resources[i]).div_ceil(self.robots[i]));
+            }
         }
+        Some(wait)
     }
 
-    fn add_geode_robot(&self, blueprint: &Blueprint) -> Option<Self> {
-        let (ore, obsidian) = blueprint.geode;
-        if self.ore >= ore && self.obsidian >= obsidian {
-            let adjusted = self.tick();
-            Some(Self {
-                geode_robots: adjusted.geode_robots + 1,
-                ore: adjusted.ore - ore,
-                obsidian: adjusted.obsidian - obsidian,
-                ..adjusted
-            })
-        } else {
-            None
-        }
+    // Target resource at the end if nothing else gets built.
+    fn idle_target(&self, blueprint: &Blueprint, minutes_left: u16) -> u16 {
+        let target = blueprint.target();
+        self.resources[target] + self.robots[target] * minutes_left
     }
 
-    fn tick(&self) -> Self {
-        Self {
-            ore: self.ore + self.ore_robots,
-            clay: self.clay + self.clay_robots,
-            obsidian: self.obsidian + self.obsidian_robots,
-            geode: self.geode + self.geode_robots,
-            ..*self
+    // Target resource collected in a relaxed game where a robot of every kind can be built in
+    // the same minute, each paid from its own copy of the resources. The relaxed game has at
+    // least as many robots of each kind at every minute, so it never collects less.
+    fn target_bound(&self, blueprint: &Blueprint, minutes_left: u16) -> u16 {
+        let kinds = blueprint.kinds();
+        let target = blueprint.target();
+        let mut robots = self.robots.clone();
+        // The wallet of each robot, one after the other.
+        let mut wallets = self.resources.repeat(kinds);
+        let mut built = vec![false; kinds];
+        let mut collected = self.resources[target];
+        for _ in 0..minutes_left {
+            for (robot, wallet) in wallets.chunks(kinds).enumerate() {
+                let cost = blueprint.cost(robot);
+                built[robot] = cost.iter().zip(wallet).all(|(n, have)| have >= n);
+            }
+            collected += robots[target];
+            for (robot, wallet) in wallets.chunks_mut(kinds).enumerate() {
+                for (have, n) in wallet.iter_mut().zip(&robots) {
+                    *have += n;
+                }
+                if built[robot] {
+                    for (have, n) in wallet.iter_mut().zip(blueprint.cost(robot)) {
+                        *have -= n;
+                    }
+                }
+            }
+            for (robots, built) in robots.iter_mut().zip(&built) {
+                *robots += *built as u16;
+            }
         }
+        collected
     }
 }
 
-fn backtrack(
-    minutes_left: u16,
-    state: State,
-    blueprint: &Blueprint,
-    total_result: &mut u16,
-) -> u16 {
-    if minutes_left == 0 {
-        if *total_result < state.geode {
-            *total_result = state.geode;
+struct Search<'a> {
+    blueprint: &'a Blueprint,
+    minutes: u16,
+    memo: HashMap<(u16, State), u16>,
+    best: u16,
+    // Minute and robot of each build on the current branch, and on the best one found.
+    path: Vec<(u16, usize)>,
+    best_path: Vec<(u16, usize)>,
+}
+
+impl Search<'_> {
+    // Branches on the next robot to build and skips the minutes spent waiting for it. Results
+    // of pruned subtrees may be low, and get memoized as they are, but only when they could
+    // not beat `best` anyway, so `best` still ends up as the optimum.
+    fn run(&mut self, minutes_left: u16, state: State) -> u16 {
+        let blueprint = self.blueprint;
+        let mut result = state.idle_target(blueprint, minutes_left);
+        if result > self.best {
+            self.best = result;
+            self.best_path.clone_from(&self.path);
         }
-        return state.geode;
-    }
+        if state.target_bound(blueprint, minutes_left) <= self.best {
+            return result;
+        }
+        let key = (minutes_left, state);
+        if let Some(&cached) = self.memo.get(&key) {
+            return cached;
+        }
+        let state = &key.1;
 
-    let mut result = state.geode;
+        // Most valuable first, so that good results are found early and prune more.
+        for robot in (0..blueprint.kinds()).rev() {
+            if state.robots[robot] >= blueprint.max_robots[robot] {
+                continue;
+            }
+            let Some(wait) = state.wait_for(blueprint.cost(robot)) else {
+                continue;
+            };
+            // A robot finished in the last minute collects nothing.
+            if wait + 1 >= minutes_left {
+                continue;
+            }
+            let next = state.clone().tick(wait).build(blueprint, robot);
+            self.path
+                .push((self.minutes - minutes_left + wait + 1, robot));
+            result = result.max(self.run(minutes_left - wait - 1, next));
+            self.path.pop();
+        }
 
-    // Empiric pruning that works fine for test & my input.
-    // TODO: Implement proper pruning or come up with DP.
-    if state.geode + (4 + state.geode_robots) * minutes_left < *total_result {
-        return result;
+        self.memo.insert(key, result);
+        result
     }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Minute {
+    pub minute: u16,
+    // Robot started this minute, which is ready at its end.
+    pub build: Option<usize>,
+    // Robots and resources of each kind at the end of the minute.
+    pub robots: Vec<u16>,
+    pub resources: Vec<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    blueprint: Blueprint,
+    // Amount of the last resource at the end.
+    pub collected: u16,
+    pub minutes: Vec<Minute>,
+}
 
-    if let Some(next_state) = state.add_geode_robot(blueprint) {
-        let next_result = backtrack(minutes_left - 1, next_state, blueprint, total_result);
-        if next_result > result {
-            result = next_result;
+impl Plan {
+    // Plays the builds, given as minute and robot, panicking on one that is not affordable.
+    fn replay(blueprint: &Blueprint, minutes: u16, builds: &[(u16, usize)]) -> Plan {
+        let mut state = State::new(blueprint);
+        let mut builds = builds.iter().peekable();
+        let mut steps = vec![];
+        for minute in 1..=minutes {
+            let build = builds
+                .next_if(|(m, _)| *m == minute)
+                .map(|&(_, robot)| robot);
+            state = match build {
+                Some(robot) => {
+                    assert_eq!(
+                        state.wait_for(blueprint.cost(robot)),
+                        Some(0),
+                        "Cannot afford {} robot in minute {}",
+                        blueprint.names[robot],
+                        minute
+                    );
+                    state.build(blueprint, robot)
+                }
+                None => state.tick(1),
+            };
+            steps.push(Minute {
+                minute,
+                build,
+                robots: state.robots.clone(),
+                resources: state.resources.clone(),
+            });
         }
-    }
-    if let Some(next_state) = state.add_obsidian_robot(blueprint) {
-        let next_result = backtrack(minutes_left - 1, next_state, blueprint, total_result);
-        if next_result > result {
-            result = next_result;
+        assert!(builds.next().is_none(), "Builds past the last minute");
+        Plan {
+            blueprint: blueprint.clone(),
+            collected: state.resources[blueprint.target()],
+            minutes: steps,
         }
     }
-    if let Some(next_state) = state.add_clay_robot(blueprint) {
-        let next_result = backtrack(minutes_left - 1, next_state, blueprint, total_result);
-        if next_result > result {
-            result = next_result;
+
+    fn robot_name(&self, robot: usize) -> String {
+        match self.blueprint.names[robot].as_str() {
+            "geode" => "geode-cracking".to_string(),
+            name => format!("{}-collecting", name),
         }
     }
-    if let Some(next_state) = state.add_ore_robot(blueprint) {
-        let next_result = backtrack(minutes_left - 1, next_state, blueprint, total_result);
-        if next_result > result {
-            result = next_result;
+}
+
+// Renders the plan the way the puzzle describes the example.
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = &self.blueprint.names;
+        for (i, step) in self.minutes.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "== Minute {} ==", step.minute)?;
+            let mut robots = step.robots.clone();
+            if let Some(robot) = step.build {
+                robots[robot] -= 1;
+                let spent = self
+                    .blueprint
+                    .cost(robot)
+                    .iter()
+                    .zip(names)
+                    .filter(|(n, _)| **n > 0)
+                    .map(|(n, name)| format!("{} {}", n, name))
+                    .collect::<Vec<_>>()
+                    .join(" and ");
+                let name = self.robot_name(robot);
+                let article = if name.starts_with(['a', 'e', 'i', 'o', 'u']) {
+                    "an"
+                } else {
+                    "a"
+                };
+                writeln!(
+                    f,
+                    "Spend {} to start building {} {} robot.",
+                    spent, article, name
+                )?;
+            }
+            for (robot, &n) in robots.iter().enumerate() {
+                if n == 0 {
+                    continue;
+                }
+                let have = step.resources[robot];
+                let plural = if n == 1 { "" } else { "s" };
+                if names[robot] == "geode" {
+                    let verb = if n == 1 { "cracks" } else { "crack" };
+                    let open = if have == 1 { "" } else { "s" };
+                    writeln!(
+                        f,
+                        "{} {} robot{} {} {} geode{}; you now have {} open geode{}.",
+                        n,
+                        self.robot_name(robot),
+                        plural,
+                        verb,
+                        n,
+                        plural,
+                        have,
+                        open
+                    )?;
+                } else {
+                    let verb = if n == 1 { "collects" } else { "collect" };
+                    writeln!(
+                        f,
+                        "{} {} robot{} {} {} {}; you now have {} {}.",
+                        n,
+                        self.robot_name(robot),
+                        plural,
+                        verb,
+                        n,
+                        names[robot],
+                        have,
+                        names[robot]
+                    )?;
+                }
+            }
+            if let Some(robot) = step.build {
+                writeln!(
+                    f,
+                    "The new {} robot is ready; you now have {} of them.",
+                    self.robot_name(robot),
+                    step.robots[robot]
+                )?;
+            }
         }
+        Ok(())
     }
+}
 
-    result.max(backtrack(
-        minutes_left - 1,
-        state.tick(),
+pub fn best_plan(blueprint: &Blueprint, minutes: u16) -> Plan {
+    let mut search = Search {
         blueprint,
-        total_result,
-    ))
+        minutes,
+        memo: HashMap::new(),
+        best: 0,
+        path: vec![],
+        best_path: vec![],
+    };
+    search.run(minutes, State::new(blueprint));
+    Plan::replay(blueprint, minutes, &search.best_path)
 }
 
 #[aoc(day19, part1)]
@@ -184,14 +433,7 @@ pub fn part1(input: &str) -> usize {
     blueprints
         .par_iter()
         .enumerate()
-        .map(|(i, blueprint)| {
-            let mut res = 0;
-            println!(
-                "Result: {}, blueprint: {blueprint:?}",
-                backtrack(24, State::new(), blueprint, &mut res)
-            );
-            (res as usize) * (i + 1)
-        })
+        .map(|(i, blueprint)| (best_plan(blueprint, 24).collected as usize) * (i + 1))
         .sum()
 }
 
@@ -201,20 +443,14 @@ pub fn part2(input: &str) -> usize {
     blueprints
         .par_iter()
         .take(3)
-        .map(|blueprint| {
-            let mut res = 0;
-            println!(
-                "Result: {}, blueprint: {blueprint:?}",
-                backtrack(32, State::new(), blueprint, &mut res)
-            );
-            res as usize
-        })
+        .map(|blueprint| best_plan(blueprint, 32).collected as usize)
         .product()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_rng::Rng;
 
     #[test]
     fn test_example_part1() {
@@ -227,4 +463,225 @@ mod tests {
         let example = include_str!("examples/day19.txt");
         assert_eq!(part2(example), 56 * 62);
     }
+
+    // Every choice in every minute, with no caps or bounds.
+    fn unpruned(
+        minutes_left: u16,
+        state: State,
+        blueprint: &Blueprint,
+        memo: &mut HashMap<(u16, State), u16>,
+    ) -> u16 {
+        if minutes_left == 0 {
+            return state.resources[blueprint.target()];
+        }
+        let key = (minutes_left, state);
+        if let Some(&cached) = memo.get(&key) {
+            return cached;
+        }
+        let state = &key.1;
+        let mut result = unpruned(minutes_left - 1, state.clone().tick(1), blueprint, memo);
+        for robot in 0..blueprint.kinds() {
+            if state.wait_for(blueprint.cost(robot)) == Some(0) {
+                let next = state.clone().build(blueprint, robot);
+                result = result.max(unpruned(minutes_left - 1, next, blueprint, memo));
+            }
+        }
+        memo.insert(key, result);
+        result
+    }
+
+    // Each robot costs some of the first resource and, past the second, some of the one before.
+    fn random_blueprint(rng: &mut Rng, names: &[&str]) -> Blueprint {
+        let costs: Vec<Vec<u16>> = (0..names.len())
+            .map(|robot| {
+                let mut cost = vec![0; names.len()];
+                cost[0] = rng.between(1, 4) as u16;
+                if robot > 1 {
+                    cost[robot - 1] = rng.between(1, 4) as u16;
+                }
+                cost
+            })
+            .collect();
+        Blueprint::new(names, &costs).unwrap()
+    }
+
+    #[test]
+    fn test_blueprint_errors() {
+        let names = ["a", "b"];
+        let costs = [vec![1, 0], vec![1, 0]];
+        assert_eq!(Blueprint::new(&[], &[]), Err(BlueprintError::NoResources));
+        assert_eq!(
+            Blueprint::new(&names[..2], &costs[..1]),
+            Err(BlueprintError::WrongRecipes(1))
+        );
+        assert_eq!(
+            Blueprint::new(&names[..2], &[vec![1, 0], vec![1]]),
+            Err(BlueprintError::WrongCost(1))
+        );
+    }
+
+    #[test]
+    fn test_parse_blueprint() {
+        let example = include_str!("examples/day19.txt");
+        let blueprint = parse_blueprint(example.lines().next().unwrap());
+        assert_eq!(blueprint.resources(), ["ore", "clay", "obsidian", "geode"]);
+        assert_eq!(blueprint.cost(0), [4, 0, 0, 0]);
+        assert_eq!(blueprint.cost(2), [3, 14, 0, 0]);
+        assert_eq!(blueprint.cost(3), [2, 0, 7, 0]);
+        assert_eq!(blueprint.max_robots, [4, 14, 7, u16::MAX]);
+
+        let variant = parse_blueprint(
+            "Blueprint 1: Each ore robot costs 2 ore. Each clay robot costs 2 ore. \
+             Each crystal robot costs 1 ore and 3 clay. Each diamond robot costs 2 crystal.",
+        );
+        assert_eq!(variant.resources(), ["ore", "clay", "crystal", "diamond"]);
+        assert_eq!(variant.cost(3), [0, 0, 2, 0]);
+    }
+
+    // Resources no robot costs are never collected, however many there are.
+    #[test]
+    fn test_unused_resources() {
+        let example = include_str!("examples/day19.txt");
+        let line = example.lines().next().unwrap();
+        let unused: String = (0..10)
+            .map(|i| format!(" Each unused{i} robot costs 1 ore."))
+            .collect();
+        let padded = line.replace(" Each geode", &format!("{unused} Each geode"));
+        let blueprint = parse_blueprint(&padded);
+        assert_eq!(blueprint.resources().len(), 14);
+        assert_eq!(best_plan(&blueprint, 24).collected, 9);
+    }
+
+    #[test]
+    fn test_example_blueprints() {
+        let example = include_str!("examples/day19.txt");
+        let blueprints: Vec<Blueprint> = example.lines().map(parse_blueprint).collect();
+        assert_eq!(best_plan(&blueprints[0], 24).collected, 9);
+        assert_eq!(best_plan(&blueprints[1], 24).collected, 12);
+        assert_eq!(best_plan(&blueprints[0], 32).collected, 56);
+        assert_eq!(best_plan(&blueprints[1], 32).collected, 62);
+    }
+
+    #[test]
+    fn test_matches_unpruned_search() {
+        let mut rng = Rng::new(0x853c_49e6_748f_ea9b);
+        let names = ["ore", "clay", "obsidian", "geode"];
+        for _ in 0..40 {
+            let mut next = |lo, hi| rng.between(lo, hi) as u16;
+            let costs = [
+                vec![next(1, 4), 0, 0, 0],
+                vec![next(1, 3), 0, 0, 0],
+                vec![next(1, 4), next(1, 4), 0, 0],
+                vec![next(1, 4), 0, next(1, 4), 0],
+            ];
+            let blueprint = Blueprint::new(&names, &costs).unwrap();
+            let minutes = rng.between(10, 12) as u16;
+            let expected = unpruned(
+                minutes,
+                State::new(&blueprint),
+                &blueprint,
+                &mut HashMap::new(),
+            );
+            let plan = best_plan(&blueprint, minutes);
+            assert_eq!(plan.collected, expected, "{blueprint:?}");
+            assert_eq!(plan.minutes.last().unwrap().resources[3], expected);
+        }
+    }
+
+    #[test]
+    fn test_extra_tiers_match_unpruned_search() {
+        let mut rng = Rng::new(0x2545_f491_4f6c_dd1d);
+        for names in [&["ore", "clay", "geode"][..], &["a", "b", "c", "d", "e"]] {
+            for _ in 0..10 {
+                let blueprint = random_blueprint(&mut rng, names);
+                let expected =
+                    unpruned(11, State::new(&blueprint), &blueprint, &mut HashMap::new());
+                assert_eq!(
+                    best_plan(&blueprint, 11).collected,
+                    expected,
+                    "{blueprint:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_target_bound_is_admissible() {
+        let example = include_str!("examples/day19.txt");
+        for blueprint in example.lines().map(parse_blueprint) {
+            let mut memo = HashMap::new();
+            let best = unpruned(16, State::new(&blueprint), &blueprint, &mut memo);
+            assert!(State::new(&blueprint).target_bound(&blueprint, 16) >= best);
+            for ((minutes_left, state), &result) in &memo {
+                let minutes_left = *minutes_left;
+                assert!(state.target_bound(&blueprint, minutes_left) >= result);
+            }
+        }
+    }
+
+    #[test]
+    fn test_plan_narrative() {
+        let example = include_str!("examples/day19.txt");
+        let blueprint = parse_blueprint(example.lines().next().unwrap());
+        // The build order walked through in the puzzle.
+        let builds = [
+            (3, 1),
+            (5, 1),
+            (7, 1),
+            (11, 2),
+            (12, 1),
+            (15, 2),
+            (18, 3),
+            (21, 3),
+        ];
+        let plan = Plan::replay(&blueprint, 24, &builds);
+        assert_eq!(plan.collected, 9);
+        let text = plan.to_string();
+        assert!(text.starts_with(
+            "== Minute 1 ==\n\
+             1 ore-collecting robot collects 1 ore; you now have 1 ore.\n\
+             \n\
+             == Minute 2 ==\n\
+             1 ore-collecting robot collects 1 ore; you now have 2 ore.\n\
+             \n\
+             == Minute 3 ==\n\
+             Spend 2 ore to start building a clay-collecting robot.\n\
+             1 ore-collecting robot collects 1 ore; you now have 1 ore.\n\
+             The new clay-collecting robot is ready; you now have 1 of them.\n\
+             \n\
+             == Minute 4 ==\n\
+             1 ore-collecting robot collects 1 ore; you now have 2 ore.\n\
+             1 clay-collecting robot collects 1 clay; you now have 1 clay.\n"
+        ));
+        assert!(text.contains(
+            "== Minute 11 ==\n\
+             Spend 3 ore and 14 clay to start building an obsidian-collecting robot.\n"
+        ));
+        assert!(text.contains(
+            "== Minute 18 ==\n\
+             Spend 2 ore and 7 obsidian to start building a geode-cracking robot.\n"
+        ));
+        assert!(text.ends_with(
+            "== Minute 24 ==\n\
+             1 ore-collecting robot collects 1 ore; you now have 6 ore.\n\
+             4 clay-collecting robots collect 4 clay; you now have 41 clay.\n\
+             2 obsidian-collecting robots collect 2 obsidian; you now have 8 obsidian.\n\
+             2 geode-cracking robots crack 2 geodes; you now have 9 open geodes.\n"
+        ));
+    }
+
+    #[test]
+    fn test_best_plan_replays() {
+        let example = include_str!("examples/day19.txt");
+        for blueprint in example.lines().map(parse_blueprint) {
+            let plan = best_plan(&blueprint, 24);
+            let builds: Vec<(u16, usize)> = plan
+                .minutes
+                .iter()
+                .filter_map(|m| m.build.map(|robot| (m.minute, robot)))
+                .collect();
+            assert_eq!(Plan::replay(&blueprint, 24, &builds), plan);
+            assert_eq!(plan.minutes.len(), 24);
+        }
+    }
 }
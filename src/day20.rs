@@ -1,78 +1,397 @@
+use std::fmt;
+
 use aoc_runner_derive::aoc;
 
 fn read_input(input: &str) -> Vec<i64> {
     input.lines().flat_map(|l| l.parse()).collect()
 }
 
-fn mix(input: &Vec<i64>, times: usize) -> Vec<i64> {
-    let mut pos: Vec<(usize, bool)> = (0..input.len()).map(|i| (i, false)).collect();
-    let n = input.len() as i64;
-    for _ in 0..times {
-        while let Some((i, &(idx, _))) = pos
-            .iter()
-            .enumerate()
-            .filter(|(_pos, (_, is_visited))| !is_visited)
-            .min_by_key(|(_, (idx, _))| idx)
-        {
-            let i = i as i64;
-            pos[i as usize] = (idx, true);
-            let mut shift = input[idx] % (n - 1);
-            if i + shift >= n {
-                shift = shift - n + 1
-            } else if i + shift < 0 {
-                shift = n + shift - 1
-            }
-            let sign = shift.signum();
-            for s in 0..shift.abs() {
-                pos.swap((i + s * sign) as usize, (i + (s + 1) * sign) as usize);
+// The list split into blocks of about `sqrt(n)` numbers, given by original index. A move scans
+// block lengths and then shifts numbers within two blocks, all of it contiguous memory.
+struct Blocks {
+    blocks: Vec<Vec<u32>>,
+    // Block holding each number.
+    block_of: Vec<u32>,
+    block_size: usize,
+}
+
+impl Blocks {
+    fn new(n: usize) -> Self {
+        let block_size = ((n as f64).sqrt() as usize).max(16);
+        let mut blocks = Self {
+            blocks: vec![],
+            block_of: vec![0; n],
+            block_size,
+        };
+        blocks.rebuild((0..n as u32).collect());
+        blocks
+    }
+
+    fn rebuild(&mut self, order: Vec<u32>) {
+        self.blocks = order
+            .chunks(self.block_size)
+            .map(|chunk| chunk.to_vec())
+            .collect();
+        for (b, block) in self.blocks.iter().enumerate() {
+            for &i in block {
+                self.block_of[i as usize] = b as u32;
             }
         }
-        for (_idx, is_visited) in pos.iter_mut() {
-            *is_visited = false;
+    }
+
+    fn order(&self) -> Vec<u32> {
+        self.blocks.concat()
+    }
+
+    // Current position of the number, along with its block and offset in there.
+    fn find(&self, i: u32) -> (usize, usize, usize) {
+        let b = self.block_of[i as usize] as usize;
+        let before: usize = self.blocks[..b].iter().map(|block| block.len()).sum();
+        let offset = self.blocks[b].iter().position(|&x| x == i).unwrap();
+        (before + offset, b, offset)
+    }
+
+    fn move_to(&mut self, (b, offset): (usize, usize), to: usize) {
+        let i = self.blocks[b].remove(offset);
+        let mut before = 0;
+        let mut b = 0;
+        while before + self.blocks[b].len() < to {
+            before += self.blocks[b].len();
+            b += 1;
+        }
+        self.blocks[b].insert(to - before, i);
+        self.block_of[i as usize] = b as u32;
+        if self.blocks[b].len() > 2 * self.block_size {
+            self.rebuild(self.order());
+        }
+    }
+}
+
+// Moves numbers, in the original order and starting over after the last one, by their value
+// along the circle. A number passing one end of the list wraps around to the other, so the
+// result is the puzzle's circle starting at some rotation, as original indices.
+fn mix_order(input: &[i64], moves: usize) -> Vec<usize> {
+    let n = input.len() as i64;
+    if n <= 1 {
+        return (0..input.len()).collect();
+    }
+    let mut blocks = Blocks::new(input.len());
+    for (i, value) in input.iter().enumerate().cycle().take(moves) {
+        let (from, b, offset) = blocks.find(i as u32);
+        let mut to = from as i64 + value % (n - 1);
+        if to >= n {
+            to -= n - 1;
+        } else if to < 0 {
+            to += n - 1;
         }
+        blocks.move_to((b, offset), to as usize);
+    }
+    blocks.order().into_iter().map(|i| i as usize).collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MixError {
+    NoAnchor(i64),
+    // Number that overflows once multiplied by the key.
+    Overflow(i64),
+}
+
+impl fmt::Display for MixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MixError::NoAnchor(anchor) => write!(f, "No anchor {anchor} in the list"),
+            MixError::Overflow(x) => write!(f, "{x} overflows when multiplied by the key"),
+        }
+    }
+}
+
+impl std::error::Error for MixError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mixer {
+    // Multiplies every number before mixing.
+    pub key: i64,
+    pub rounds: usize,
+    // Number the mixed circle is read from, as given before applying the key. If it repeats,
+    // the first one in the list.
+    pub anchor: i64,
+    // Positions after the anchor that make up the grove coordinates.
+    pub offsets: Vec<usize>,
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Mixer {
+            key: 1,
+            rounds: 1,
+            anchor: 0,
+            offsets: vec![1000, 2000, 3000],
+        }
+    }
+}
+
+impl Mixer {
+    pub fn decryption() -> Self {
+        Mixer {
+            key: 811589153,
+            rounds: 10,
+            ..Default::default()
+        }
+    }
+
+    // The circle after the first `moves` moves, starting at the anchor.
+    pub fn mixed_after(&self, numbers: &[i64], moves: usize) -> Result<Vec<i64>, MixError> {
+        let anchor = numbers
+            .iter()
+            .position(|&x| x == self.anchor)
+            .ok_or(MixError::NoAnchor(self.anchor))?;
+        let keyed = numbers
+            .iter()
+            .map(|&x| x.checked_mul(self.key).ok_or(MixError::Overflow(x)))
+            .collect::<Result<Vec<i64>, _>>()?;
+        let mut order = mix_order(&keyed, moves);
+        let start = order.iter().position(|&i| i == anchor).unwrap();
+        order.rotate_left(start);
+        Ok(order.into_iter().map(|i| keyed[i]).collect())
+    }
+
+    pub fn mix(&self, numbers: &[i64]) -> Result<Vec<i64>, MixError> {
+        self.mixed_after(numbers, self.rounds * numbers.len())
+    }
+
+    pub fn coordinates(&self, numbers: &[i64]) -> Result<Vec<i64>, MixError> {
+        let mixed = self.mix(numbers)?;
+        Ok(self
+            .offsets
+            .iter()
+            .map(|offset| mixed[offset % mixed.len()])
+            .collect())
     }
-    pos.iter().map(|(i, _)| input[*i]).collect()
 }
 
 #[aoc(day20, part1)]
-pub fn part1(input: &str) -> i64 {
-    let input = read_input(input);
-    let res = mix(&input, 1);
-    res.into_iter()
-        .cycle()
-        .skip_while(|&x| x != 0)
-        .step_by(1000)
-        .skip(1)
-        .take(3)
-        .sum()
+pub fn part1(input: &str) -> Result<i64, MixError> {
+    Ok(Mixer::default()
+        .coordinates(&read_input(input))?
+        .iter()
+        .sum())
 }
 
 #[aoc(day20, part2)]
-pub fn part2(input: &str) -> i64 {
-    let input: Vec<i64> = read_input(input).iter().map(|x| x * 811589153).collect();
-    let res = mix(&input, 10);
-    res.into_iter()
-        .cycle()
-        .skip_while(|&x| x != 0)
-        .step_by(1000)
-        .skip(1)
-        .take(3)
-        .sum()
+pub fn part2(input: &str) -> Result<i64, MixError> {
+    Ok(Mixer::decryption()
+        .coordinates(&read_input(input))?
+        .iter()
+        .sum())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_rng::Rng;
+
+    fn mix(input: &[i64], moves: usize) -> Vec<i64> {
+        mix_order(input, moves)
+            .into_iter()
+            .map(|i| input[i])
+            .collect()
+    }
 
     #[test]
     fn test_example_part1() {
         let example = include_str!("examples/day20.txt");
-        assert_eq!(part1(example), 3);
+        assert_eq!(part1(example), Ok(3));
     }
 
     #[test]
     fn test_example_part2() {
         let example = include_str!("examples/day20.txt");
-        assert_eq!(part2(example), 1623178306);
+        assert_eq!(part2(example), Ok(1623178306));
+    }
+
+    // Moves each number with adjacent swaps.
+    fn naive_mix(input: &[i64], times: usize) -> Vec<i64> {
+        let mut pos: Vec<usize> = (0..input.len()).collect();
+        let n = input.len() as i64;
+        for _ in 0..times {
+            for (idx, value) in input.iter().enumerate() {
+                let i = pos.iter().position(|&p| p == idx).unwrap() as i64;
+                let mut shift = value % (n - 1);
+                if i + shift >= n {
+                    shift = shift - n + 1
+                } else if i + shift < 0 {
+                    shift = n + shift - 1
+                }
+                let sign = shift.signum();
+                for s in 0..shift.abs() {
+                    pos.swap((i + s * sign) as usize, (i + (s + 1) * sign) as usize);
+                }
+            }
+        }
+        pos.iter().map(|&i| input[i]).collect()
+    }
+
+    #[test]
+    fn test_example_mix() {
+        let example = read_input(include_str!("examples/day20.txt"));
+        assert_eq!(mix(&example, 7), vec![-2, 1, 2, -3, 4, 0, 3]);
+        assert_eq!(mix(&example, 7), naive_mix(&example, 1));
+    }
+
+    #[test]
+    fn test_mix_matches_naive() {
+        let mut rng = Rng::new(0x2022_1220);
+        let mut next = || rng.next_u64();
+        for len in [2, 3, 5, 10, 50, 200] {
+            let input: Vec<i64> = (0..len)
+                .map(|_| {
+                    let x = (next() % 2001) as i64 - 1000;
+                    if next() % 4 == 0 {
+                        x * 811589153
+                    } else {
+                        x
+                    }
+                })
+                .collect();
+            for times in [1, 3] {
+                assert_eq!(
+                    mix(&input, times * input.len()),
+                    naive_mix(&input, times),
+                    "{input:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_mix_large() {
+        let n = 200_000_i64;
+        let input: Vec<i64> = (0..n).map(|i| (i * 7919) % n - n / 2).collect();
+        let mut mixed = mix(&input, input.len());
+        assert_eq!(mixed.len(), input.len());
+        mixed.sort_unstable();
+        let mut sorted = input.clone();
+        sorted.sort_unstable();
+        assert_eq!(mixed, sorted);
+    }
+
+    // Run with `cargo test --release -- --ignored bench_mix_million --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_mix_million() {
+        let n = 1_000_000_i64;
+        let input: Vec<i64> = (0..n).map(|i| (i * 7919) % n - n / 2).collect();
+        let now = std::time::Instant::now();
+        let mut mixed = Mixer::decryption().mix(&input).unwrap();
+        println!("{} numbers, 10 rounds: {:?}", n, now.elapsed());
+        mixed.sort_unstable();
+        let mut sorted: Vec<i64> = input.iter().map(|x| x * 811589153).collect();
+        sorted.sort_unstable();
+        assert_eq!(mixed, sorted);
+    }
+
+    #[test]
+    fn test_example_steps() {
+        let example = read_input(include_str!("examples/day20.txt"));
+        let mixer = Mixer::default();
+        // The puzzle's listing after each move, read from 0.
+        let steps = [
+            [0, 4, 1, 2, -3, 3, -2],
+            [0, 4, 2, 1, -3, 3, -2],
+            [0, 4, 1, -3, 2, 3, -2],
+            [0, 4, 1, 2, 3, -2, -3],
+            [0, 3, 4, 1, 2, -2, -3],
+            [0, 3, 4, -2, 1, 2, -3],
+            [0, 3, 4, -2, 1, 2, -3],
+            [0, 3, -2, 1, 2, -3, 4],
+        ];
+        for (moves, step) in steps.iter().enumerate() {
+            assert_eq!(
+                mixer.mixed_after(&example, moves).unwrap(),
+                step,
+                "after {moves} moves"
+            );
+        }
+        assert_eq!(mixer.coordinates(&example).unwrap(), vec![4, -3, 2]);
+    }
+
+    #[test]
+    fn test_example_rounds() {
+        let example = read_input(include_str!("examples/day20.txt"));
+        let mixer = Mixer::decryption();
+        assert_eq!(
+            mixer.mixed_after(&example, 7).unwrap(),
+            vec![
+                0,
+                -2434767459,
+                3246356612,
+                -1623178306,
+                2434767459,
+                1623178306,
+                811589153
+            ]
+        );
+        assert_eq!(
+            mixer.mix(&example).unwrap(),
+            vec![
+                0,
+                -2434767459,
+                1623178306,
+                3246356612,
+                -1623178306,
+                2434767459,
+                811589153
+            ]
+        );
+        assert_eq!(
+            mixer.coordinates(&example).unwrap(),
+            vec![811589153, 2434767459, -1623178306]
+        );
+    }
+
+    #[test]
+    fn test_custom_mixer() {
+        let example = read_input(include_str!("examples/day20.txt"));
+        let mixer = Mixer {
+            key: 2,
+            rounds: 2,
+            anchor: 4,
+            offsets: vec![1, 2],
+        };
+        let mixed = mixer.mix(&example).unwrap();
+        assert_eq!(mixed[0], 8);
+        // Only the reading changes with the anchor.
+        let mut from_zero = Mixer {
+            anchor: 0,
+            ..mixer.clone()
+        }
+        .mix(&example)
+        .unwrap();
+        from_zero.rotate_left(4);
+        assert_eq!(mixed, from_zero);
+        assert_eq!(mixer.coordinates(&example).unwrap(), mixed[1..3]);
+    }
+
+    #[test]
+    fn test_mixer_anchor_and_errors() {
+        // Mixing gives the circle 3, 1, 2, 2 where the first 2 of the list is the last one.
+        let numbers = [2, 1, 3, 2];
+        let mixer = Mixer {
+            anchor: 2,
+            ..Default::default()
+        };
+        assert_eq!(mix(&numbers, numbers.len()), vec![3, 1, 2, 2]);
+        assert_eq!(mixer.mix(&numbers), Ok(vec![2, 3, 1, 2]));
+
+        let mixer = Mixer {
+            anchor: 5,
+            ..Default::default()
+        };
+        assert_eq!(mixer.mix(&numbers), Err(MixError::NoAnchor(5)));
+        let numbers = [0, i64::MAX / 2];
+        assert_eq!(
+            Mixer::decryption().mix(&numbers),
+            Err(MixError::Overflow(i64::MAX / 2))
+        );
     }
 }
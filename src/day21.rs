@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
 use aoc_runner_derive::aoc;
 use itertools::Itertools;
@@ -64,39 +64,574 @@ fn unapply_right_op(op: &str, right: i64, target: i64) -> i64 {
     }
 }
 
-#[aoc(day21, part1)]
-pub fn part1(input: &str) -> i64 {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Op {
+    fn parse(op: &str) -> Op {
+        match op {
+            "+" => Op::Add,
+            "-" => Op::Sub,
+            "*" => Op::Mul,
+            "/" => Op::Div,
+            unknown => panic!("Unknown operation: {unknown}"),
+        }
+    }
+
+    // Exact result, or `None` when dividing by zero or on overflow.
+    fn apply_exact(&self, left: Rational, right: Rational) -> Option<Rational> {
+        match self {
+            Op::Add => left.checked_add(right),
+            Op::Sub => left.checked_sub(right),
+            Op::Mul => left.checked_mul(right),
+            Op::Div => left.checked_div(right),
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Op::Add | Op::Sub => 1,
+            Op::Mul | Op::Div => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Job {
+    Number(i64),
+    Op(Op, usize, usize),
+    // Listened to, but never given a job.
+    Undefined,
+}
+
+// Monkey jobs as a DAG over monkey ids, so that a monkey listened to by several others is
+// only one node.
+pub struct Monkeys<'a> {
+    names: Vec<&'a str>,
+    index: HashMap<&'a str, usize>,
+    jobs: Vec<Job>,
+}
+
+impl<'a> Monkeys<'a> {
+    pub fn parse(input: &'a str) -> Self {
+        Self::compile(&Input::parse(input))
+    }
+
+    fn compile(input: &HashMap<&'a str, Input<'a>>) -> Self {
+        // Every monkey mentioned, including the ones listened to without a job of their own.
+        let mut names: Vec<&str> = input
+            .iter()
+            .flat_map(|(&name, job)| match *job {
+                Input::Number { .. } => vec![name],
+                Input::Op { left, right, .. } => vec![name, left, right],
+            })
+            .unique()
+            .collect();
+        names.sort_unstable();
+        let index: HashMap<&str, usize> = names.iter().enumerate().map(|(i, &n)| (n, i)).collect();
+        let jobs = names
+            .iter()
+            .map(|name| match input.get(name) {
+                Some(Input::Number { val }) => Job::Number(*val),
+                Some(Input::Op { left, right, op }) => {
+                    Job::Op(Op::parse(op), index[left], index[right])
+                }
+                None => Job::Undefined,
+            })
+            .collect();
+        Self { names, index, jobs }
+    }
+
+    fn id(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    // Value of the monkey as `a * humn + b`, and whether `humn` occurs in its expression at all.
+    // `visiting` marks the monkeys whose value is being worked out, to catch cycles.
+    fn linear(
+        &self,
+        id: usize,
+        human: usize,
+        memo: &mut Vec<Option<Linear>>,
+        visiting: &mut Vec<bool>,
+    ) -> Result<Linear, SolveError> {
+        if let Some(linear) = memo[id] {
+            return Ok(linear);
+        }
+        if visiting[id] {
+            return Err(SolveError::Cycle(self.names[id].to_string()));
+        }
+        visiting[id] = true;
+        let monkey = || self.names[id].to_string();
+        let overflow = || SolveError::Overflow(self.names[id].to_string());
+        let linear = match self.jobs[id] {
+            _ if id == human => Linear {
+                a: Rational::from(1),
+                b: Rational::from(0),
+                human: true,
+            },
+            Job::Number(val) => Linear {
+                a: Rational::from(0),
+                b: Rational::from(val),
+                human: false,
+            },
+            Job::Undefined => return Err(SolveError::Undefined(monkey())),
+            Job::Op(op, left, right) => {
+                let l = self.linear(left, human, memo, visiting)?;
+                let r = self.linear(right, human, memo, visiting)?;
+                let human = l.human || r.human;
+                let (a, b) = match op {
+                    Op::Add => (l.a.checked_add(r.a), l.b.checked_add(r.b)),
+                    Op::Sub => (l.a.checked_sub(r.a), l.b.checked_sub(r.b)),
+                    Op::Mul if l.human && r.human => {
+                        return Err(SolveError::NonLinear(monkey()));
+                    }
+                    Op::Mul => {
+                        let (scaled, factor) = if l.human { (l, r.b) } else { (r, l.b) };
+                        (scaled.a.checked_mul(factor), scaled.b.checked_mul(factor))
+                    }
+                    Op::Div if r.human => return Err(SolveError::NonLinear(monkey())),
+                    Op::Div if r.b.is_zero() => return Err(SolveError::DivisionByZero(monkey())),
+                    Op::Div => (l.a.checked_div(r.b), l.b.checked_div(r.b)),
+                };
+                Linear {
+                    a: a.ok_or_else(overflow)?,
+                    b: b.ok_or_else(overflow)?,
+                    human,
+                }
+            }
+        };
+        visiting[id] = false;
+        memo[id] = Some(linear);
+        Ok(linear)
+    }
+
+    // Finds the number `human` has to yell for both sides of `root` to be equal.
+    pub fn solve(&self, root: &str, human: &str) -> Result<i64, SolveError> {
+        let id = self
+            .id(root)
+            .ok_or_else(|| SolveError::Undefined(root.to_string()))?;
+        let Job::Op(_, left, right) = self.jobs[id] else {
+            return Err(SolveError::NoEquation(root.to_string()));
+        };
+        // A human nobody listens to is on neither side.
+        let human = self.id(human).unwrap_or(usize::MAX);
+        let mut memo = vec![None; self.jobs.len()];
+        let mut visiting = vec![false; self.jobs.len()];
+        let l = self.linear(left, human, &mut memo, &mut visiting)?;
+        let r = self.linear(right, human, &mut memo, &mut visiting)?;
+        let (unknown, known) = match (l.human, r.human) {
+            (true, true) => return Err(SolveError::HumanOnBothSides),
+            (false, false) => return Err(SolveError::NoHuman),
+            (true, false) => (l, r),
+            (false, true) => (r, l),
+        };
+        if unknown.a.is_zero() {
+            return Err(SolveError::NoUniqueSolution);
+        }
+        let x = known
+            .b
+            .checked_sub(unknown.b)
+            .and_then(|diff| diff.checked_div(unknown.a))
+            .ok_or_else(|| SolveError::Overflow(root.to_string()))?;
+        x.to_integer().ok_or(SolveError::NotInteger(x))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Notation {
+    #[default]
+    Plain,
+    Latex,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PrintOptions<'a> {
+    pub notation: Notation,
+    // Monkey printed as a variable instead of its number.
+    pub human: Option<&'a str>,
+    // Replaces subtrees without the variable by their exact value.
+    pub fold: bool,
+}
+
+impl Default for PrintOptions<'_> {
+    fn default() -> Self {
+        PrintOptions {
+            notation: Notation::Plain,
+            human: None,
+            fold: true,
+        }
+    }
+}
+
+enum Expr<'a> {
+    Number(Rational),
+    Var(&'a str),
+    Op(Op, Box<Expr<'a>>, Box<Expr<'a>>),
+}
+
+// Atoms bind tighter than any operation, and negative numbers looser.
+const ATOM: u8 = 3;
+
+impl Expr<'_> {
+    // Text of the expression along with how tightly it binds.
+    fn render(&self, notation: Notation) -> (String, u8) {
+        let latex = notation == Notation::Latex;
+        match self {
+            Expr::Number(val) if val.den == 1 => {
+                (val.num.to_string(), if val.num < 0 { 0 } else { ATOM })
+            }
+            Expr::Number(val) if latex => {
+                let frac = format!("\\frac{{{}}}{{{}}}", val.num.abs(), val.den);
+                if val.num < 0 {
+                    (format!("-{frac}"), 0)
+                } else {
+                    (frac, ATOM)
+                }
+            }
+            Expr::Number(val) => (val.to_string(), if val.num < 0 { 0 } else { 2 }),
+            Expr::Var(name) if latex => (format!("\\mathit{{{name}}}"), ATOM),
+            Expr::Var(name) => (name.to_string(), ATOM),
+            Expr::Op(op, left, right) => {
+                let (l, lp) = left.render(notation);
+                let (r, rp) = right.render(notation);
+                if latex && *op == Op::Div {
+                    return (format!("\\frac{{{l}}}{{{r}}}"), ATOM);
+                }
+                let wrap = |text: String, parens: bool| match (parens, latex) {
+                    (false, _) => text,
+                    (true, false) => format!("({text})"),
+                    (true, true) => format!("\\left({text}\\right)"),
+                };
+                let p = op.precedence();
+                let l = wrap(l, lp < p);
+                let r = wrap(r, rp < p || (rp == p && *op != Op::Add));
+                let symbol = match op {
+                    Op::Add => "+",
+                    Op::Sub => "-",
+                    Op::Mul if latex => "\\cdot",
+                    Op::Mul => "*",
+                    Op::Div => "/",
+                };
+                (format!("{l} {symbol} {r}"), p)
+            }
+        }
+    }
+}
+
+impl Monkeys<'_> {
+    // `visiting` marks the monkeys being expanded, to catch cycles.
+    fn expand(
+        &self,
+        id: usize,
+        options: &PrintOptions,
+        visiting: &mut Vec<bool>,
+    ) -> Result<Expr<'_>, SolveError> {
+        if options.human == Some(self.names[id]) {
+            return Ok(Expr::Var(self.names[id]));
+        }
+        let expr = match self.jobs[id] {
+            Job::Undefined => return Err(SolveError::Undefined(self.names[id].to_string())),
+            Job::Number(val) => Expr::Number(Rational::from(val)),
+            Job::Op(..) if visiting[id] => {
+                return Err(SolveError::Cycle(self.names[id].to_string()));
+            }
+            Job::Op(op, left, right) => {
+                visiting[id] = true;
+                let left = self.expand(left, options, visiting)?;
+                let right = self.expand(right, options, visiting)?;
+                visiting[id] = false;
+                if let (true, Expr::Number(l), Expr::Number(r)) = (options.fold, &left, &right) {
+                    if let Some(val) = op.apply_exact(*l, *r) {
+                        return Ok(Expr::Number(val));
+                    }
+                }
+                Expr::Op(op, Box::new(left), Box::new(right))
+            }
+        };
+        Ok(expr)
+    }
+
+    fn print_id(&self, id: usize, options: &PrintOptions) -> Result<String, SolveError> {
+        let mut visiting = vec![false; self.jobs.len()];
+        let expr = self.expand(id, options, &mut visiting)?;
+        Ok(expr.render(options.notation).0)
+    }
+
+    // The monkey's job with every monkey it listens to substituted in.
+    pub fn print(&self, monkey: &str, options: &PrintOptions) -> Result<String, SolveError> {
+        let id = self
+            .id(monkey)
+            .ok_or_else(|| SolveError::Undefined(monkey.to_string()))?;
+        self.print_id(id, options)
+    }
+
+    // Both sides of the monkey's job as an equation, as in part 2.
+    pub fn print_equation(&self, root: &str, options: &PrintOptions) -> Result<String, SolveError> {
+        let id = self
+            .id(root)
+            .ok_or_else(|| SolveError::Undefined(root.to_string()))?;
+        let Job::Op(_, left, right) = self.jobs[id] else {
+            return Err(SolveError::NoEquation(root.to_string()));
+        };
+        let left = self.print_id(left, options)?;
+        let right = self.print_id(right, options)?;
+        Ok(format!("{left} = {right}"))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Linear {
+    a: Rational,
+    b: Rational,
+    human: bool,
+}
+
+// Exact fraction in lowest terms, with a positive denominator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rational {
+    num: i128,
+    den: i128,
+}
+
+// `None` when a step overflows, as for i128::MIN and -1.
+fn gcd(a: i128, b: i128) -> Option<i128> {
+    if b == 0 {
+        a.checked_abs()
+    } else {
+        gcd(b, a.checked_rem(b)?)
+    }
+}
+
+impl Rational {
+    // Reduced fraction, or `None` for a zero denominator or when it does not fit.
+    fn new(num: i128, den: i128) -> Option<Self> {
+        if den == 0 {
+            return None;
+        }
+        let g = gcd(num, den)?.checked_mul(den.signum())?;
+        Some(Self {
+            num: num.checked_div(g)?,
+            den: den.checked_div(g)?,
+        })
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        Self::new(
+            self.num
+                .checked_mul(other.den)?
+                .checked_add(other.num.checked_mul(self.den)?)?,
+            self.den.checked_mul(other.den)?,
+        )
+    }
+
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        Self::new(
+            self.num
+                .checked_mul(other.den)?
+                .checked_sub(other.num.checked_mul(self.den)?)?,
+            self.den.checked_mul(other.den)?,
+        )
+    }
+
+    fn checked_mul(self, other: Self) -> Option<Self> {
+        Self::new(
+            self.num.checked_mul(other.num)?,
+            self.den.checked_mul(other.den)?,
+        )
+    }
+
+    // `None` also when dividing by zero.
+    fn checked_div(self, other: Self) -> Option<Self> {
+        Self::new(
+            self.num.checked_mul(other.den)?,
+            self.den.checked_mul(other.num)?,
+        )
+    }
+
+    fn is_zero(&self) -> bool {
+        self.num == 0
+    }
+
+    fn to_integer(self) -> Option<i64> {
+        if self.den == 1 {
+            self.num.try_into().ok()
+        } else {
+            None
+        }
+    }
+}
+
+impl From<i64> for Rational {
+    fn from(val: i64) -> Self {
+        Self {
+            num: val as i128,
+            den: 1,
+        }
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SolveError {
+    NoEquation(String),
+    NoHuman,
+    HumanOnBothSides,
+    NonLinear(String),
+    DivisionByZero(String),
+    NoUniqueSolution,
+    NotInteger(Rational),
+    Undefined(String),
+    // Monkey that ends up waiting on itself.
+    Cycle(String),
+    Overflow(String),
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveError::NoEquation(monkey) => write!(f, "{monkey} does not compare two monkeys"),
+            SolveError::NoHuman => write!(f, "humn is on neither side of the equation"),
+            SolveError::HumanOnBothSides => write!(f, "humn is on both sides of the equation"),
+            SolveError::NonLinear(monkey) => {
+                write!(f, "{monkey} is not linear in humn")
+            }
+            SolveError::DivisionByZero(monkey) => write!(f, "{monkey} divides by zero"),
+            SolveError::NoUniqueSolution => write!(f, "humn cancels out of the equation"),
+            SolveError::NotInteger(x) => write!(f, "humn would have to be {x}"),
+            SolveError::Undefined(monkey) => write!(f, "{monkey} has no job"),
+            SolveError::Cycle(monkey) => write!(f, "{monkey} waits for itself"),
+            SolveError::Overflow(monkey) => write!(f, "{monkey} overflows"),
+        }
+    }
+}
+
+impl std::error::Error for SolveError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EvalErrorKind {
+    Undefined,
+    Cycle,
+    Overflow,
+    InexactDivision,
+    DivisionByZero,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvalError {
+    pub kind: EvalErrorKind,
+    // Monkeys from the one evaluated down to where it failed. For a cycle, the monkeys around
+    // it, starting and ending with the same one.
+    pub chain: Vec<String>,
+}
+
+impl EvalError {
+    fn new(kind: EvalErrorKind, chain: &[&str]) -> Self {
+        Self {
+            kind,
+            chain: chain.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self.kind {
+            EvalErrorKind::Undefined => "Undefined monkey",
+            EvalErrorKind::Cycle => "Monkeys wait for each other",
+            EvalErrorKind::Overflow => "Overflow",
+            EvalErrorKind::InexactDivision => "Inexact division",
+            EvalErrorKind::DivisionByZero => "Division by zero",
+        };
+        write!(f, "{}: {}", what, self.chain.join(" -> "))
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+fn apply_checked(op: &str, left: i64, right: i64) -> Result<i64, EvalErrorKind> {
+    let result = match op {
+        "+" => left.checked_add(right),
+        "-" => left.checked_sub(right),
+        "*" => left.checked_mul(right),
+        "/" if right == 0 => return Err(EvalErrorKind::DivisionByZero),
+        "/" => match left.checked_rem(right) {
+            Some(0) => left.checked_div(right),
+            Some(_) => return Err(EvalErrorKind::InexactDivision),
+            None => None,
+        },
+        unknown => panic!("Unknown operation: {unknown}"),
+    };
+    result.ok_or(EvalErrorKind::Overflow)
+}
+
+// Number the monkey yells, keeping the stack of monkeys being waited on to report where
+// evaluation fails.
+pub fn evaluate(input: &str, monkey: &str) -> Result<i64, EvalError> {
     let input = Input::parse(input);
     let mut numbers: HashMap<&str, i64> = HashMap::new();
-    let mut stack: Vec<&str> = Vec::new();
-    stack.push("root");
+    let mut stack: Vec<&str> = vec![monkey];
+    if !input.contains_key(monkey) {
+        return Err(EvalError::new(EvalErrorKind::Undefined, &stack));
+    }
 
-    while let Some(name) = stack.last() {
-        match *input.get(name).unwrap() {
+    while let Some(&name) = stack.last() {
+        match input[name] {
             Input::Number { val } => {
                 numbers.insert(name, val);
                 stack.pop();
             }
-            Input::Op { left, right, op } => match (numbers.get(left), numbers.get(right)) {
-                (Some(left_val), Some(right_val)) => {
-                    numbers.insert(name, apply_op(op, *left_val, *right_val));
+            Input::Op { left, right, op } => {
+                let Some(waiting) = [left, right].into_iter().find(|m| !numbers.contains_key(m))
+                else {
+                    let val = apply_checked(op, numbers[left], numbers[right])
+                        .map_err(|kind| EvalError::new(kind, &stack))?;
+                    numbers.insert(name, val);
                     stack.pop();
+                    continue;
+                };
+                if let Some(start) = stack.iter().position(|&m| m == waiting) {
+                    let mut cycle = stack[start..].to_vec();
+                    cycle.push(waiting);
+                    return Err(EvalError::new(EvalErrorKind::Cycle, &cycle));
                 }
-                (None, _) => {
-                    stack.push(left);
-                }
-                (_, None) => {
-                    stack.push(right);
+                stack.push(waiting);
+                if !input.contains_key(waiting) {
+                    return Err(EvalError::new(EvalErrorKind::Undefined, &stack));
                 }
-            },
+            }
         }
     }
 
-    *numbers.get("root").unwrap()
+    Ok(numbers[monkey])
+}
+
+#[aoc(day21, part1)]
+pub fn part1(input: &str) -> Result<i64, EvalError> {
+    evaluate(input, "root")
 }
 
 #[aoc(day21, part2)]
-pub fn part2(input: &str) -> i64 {
+pub fn part2(input: &str) -> Result<i64, SolveError> {
+    Monkeys::parse(input).solve("root", "humn")
+}
+
+#[aoc(day21, part2, path)]
+pub fn part2_path(input: &str) -> i64 {
     let input = Input::parse(input);
     let mut numbers: HashMap<&str, i64> = HashMap::new();
     let mut path: Option<Vec<&str>> = None;
@@ -160,12 +695,259 @@ mod tests {
     #[test]
     fn test_example_part1() {
         let example = include_str!("examples/day21.txt");
-        assert_eq!(part1(example), 152);
+        assert_eq!(part1(example), Ok(152));
     }
 
     #[test]
     fn test_example_part2() {
         let example = include_str!("examples/day21.txt");
-        assert_eq!(part2(example), 301);
+        assert_eq!(part2(example), Ok(301));
+    }
+
+    #[test]
+    fn test_example_part2_path() {
+        let example = include_str!("examples/day21.txt");
+        assert_eq!(part2_path(example), 301);
+    }
+
+    fn solve(input: &str) -> Result<i64, SolveError> {
+        Monkeys::parse(input).solve("root", "humn")
+    }
+
+    #[test]
+    fn test_solve_shared_and_fractional() {
+        // `aaaa` is listened to twice and divides humn.
+        let input = "root: bbbb + cccc\n\
+                     aaaa: humn / three\n\
+                     bbbb: aaaa + aaaa\n\
+                     cccc: 10\n\
+                     three: 3\n\
+                     humn: 1";
+        assert_eq!(solve(input), Ok(15));
+        // The path search only follows one way to humn.
+        assert_ne!(part2_path(input), 15);
+
+        let input = "root: aaaa + seven\naaaa: humn * two\ntwo: 2\nseven: 7\nhumn: 1";
+        assert_eq!(
+            solve(input),
+            Err(SolveError::NotInteger(Rational::new(7, 2).unwrap()))
+        );
+    }
+
+    #[test]
+    fn test_solve_errors() {
+        let both = "root: aaaa + bbbb\naaaa: humn + one\nbbbb: humn * one\none: 1\nhumn: 1";
+        assert_eq!(solve(both), Err(SolveError::HumanOnBothSides));
+
+        let square = "root: aaaa + one\naaaa: humn * humn\none: 1\nhumn: 1";
+        assert_eq!(solve(square), Err(SolveError::NonLinear("aaaa".into())));
+
+        let inverse = "root: aaaa + one\naaaa: one / humn\none: 1\nhumn: 1";
+        assert_eq!(solve(inverse), Err(SolveError::NonLinear("aaaa".into())));
+
+        let zero = "root: aaaa + one\naaaa: humn / zero\nzero: 0\none: 1\nhumn: 1";
+        assert_eq!(solve(zero), Err(SolveError::DivisionByZero("aaaa".into())));
+
+        let cancel = "root: aaaa + one\naaaa: humn - humn\none: 1\nhumn: 1";
+        assert_eq!(solve(cancel), Err(SolveError::NoUniqueSolution));
+
+        let missing = "root: one + one\none: 1\nhumn: 1";
+        assert_eq!(solve(missing), Err(SolveError::NoHuman));
+
+        let number = "root: 1\nhumn: 1";
+        assert_eq!(solve(number), Err(SolveError::NoEquation("root".into())));
+
+        let cycle = "root: aaaa + bbbb\naaaa: bbbb * cccc\nbbbb: aaaa + humn";
+        assert_eq!(solve(cycle), Err(SolveError::Cycle("aaaa".into())));
+
+        let undefined = "root: aaaa + one\naaaa: humn * zzzz\none: 1\nhumn: 1";
+        assert_eq!(solve(undefined), Err(SolveError::Undefined("zzzz".into())));
+        assert_eq!(solve("humn: 1"), Err(SolveError::Undefined("root".into())));
+        assert_eq!(solve("root: one + one\none: 1"), Err(SolveError::NoHuman));
+
+        // 2^62 squared twice overflows the i128 fractions.
+        let overflow = "root: aaaa + humn\n\
+                        aaaa: bbbb * bbbb\n\
+                        bbbb: cccc * cccc\n\
+                        cccc: 4611686018427387904\n\
+                        humn: 1";
+        assert_eq!(solve(overflow), Err(SolveError::Overflow("aaaa".into())));
+    }
+
+    #[test]
+    fn test_print_example() {
+        let example = include_str!("examples/day21.txt");
+        let monkeys = Monkeys::parse(example);
+        let expanded = PrintOptions {
+            fold: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            monkeys.print("root", &expanded).unwrap(),
+            "(4 + 2 * (5 - 3)) / 4 + (32 - 2) * 5"
+        );
+        assert_eq!(
+            monkeys.print("root", &PrintOptions::default()).unwrap(),
+            "152"
+        );
+
+        let equation = PrintOptions {
+            human: Some("humn"),
+            ..Default::default()
+        };
+        assert_eq!(
+            monkeys.print_equation("root", &equation).unwrap(),
+            "(4 + 2 * (humn - 3)) / 4 = 150"
+        );
+        let latex = PrintOptions {
+            notation: Notation::Latex,
+            ..equation
+        };
+        assert_eq!(
+            monkeys.print_equation("root", &latex).unwrap(),
+            "\\frac{4 + 2 \\cdot \\left(\\mathit{humn} - 3\\right)}{4} = 150"
+        );
+    }
+
+    #[test]
+    fn test_print_exact_values() {
+        let input = "root: aaaa * bbbb\n\
+                     aaaa: seven / two\n\
+                     bbbb: two - seven\n\
+                     cccc: two - dddd\n\
+                     dddd: aaaa - bbbb\n\
+                     two: 2\n\
+                     seven: 7";
+        let monkeys = Monkeys::parse(input);
+        let options = PrintOptions::default();
+        assert_eq!(monkeys.print("aaaa", &options).unwrap(), "7/2");
+        assert_eq!(monkeys.print("root", &options).unwrap(), "-35/2");
+        let latex = PrintOptions {
+            notation: Notation::Latex,
+            ..options
+        };
+        assert_eq!(monkeys.print("root", &latex).unwrap(), "-\\frac{35}{2}");
+
+        // Only the variable's subtree stays.
+        let options = PrintOptions {
+            human: Some("aaaa"),
+            ..options
+        };
+        assert_eq!(monkeys.print("root", &options).unwrap(), "aaaa * (-5)");
+        assert_eq!(
+            monkeys.print("cccc", &options).unwrap(),
+            "2 - (aaaa - (-5))"
+        );
+        let options = PrintOptions {
+            human: Some("bbbb"),
+            ..options
+        };
+        assert_eq!(monkeys.print("root", &options).unwrap(), "7/2 * bbbb");
+        assert_eq!(monkeys.print("dddd", &options).unwrap(), "7/2 - bbbb");
+    }
+
+    #[test]
+    fn test_print_errors() {
+        let options = PrintOptions::default();
+        let cycle = Monkeys::parse("root: aaaa + bbbb\naaaa: bbbb * cccc\nbbbb: aaaa + humn");
+        assert_eq!(
+            cycle.print("root", &options),
+            Err(SolveError::Cycle("aaaa".into()))
+        );
+        assert_eq!(
+            cycle.print_equation("root", &options),
+            Err(SolveError::Cycle("aaaa".into()))
+        );
+        assert_eq!(
+            cycle.print("cccc", &options),
+            Err(SolveError::Undefined("cccc".into()))
+        );
+        assert_eq!(
+            cycle.print("zzzz", &options),
+            Err(SolveError::Undefined("zzzz".into()))
+        );
+
+        let number = Monkeys::parse("root: 1");
+        assert_eq!(
+            number.print_equation("root", &options),
+            Err(SolveError::NoEquation("root".into()))
+        );
+
+        // A fold that overflows leaves its operation in place.
+        let overflow = Monkeys::parse(MIN_BY_MINUS_ONE);
+        assert_eq!(
+            overflow.print("gggg", &options).unwrap(),
+            "(-170141183460469231731687303715884105728) / (-1)"
+        );
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let example = include_str!("examples/day21.txt");
+        assert_eq!(evaluate(example, "sjmn"), Ok(150));
+        assert_eq!(evaluate(example, "humn"), Ok(5));
+    }
+
+    // -2^63 * 2^62 * 4 = i128::MIN, which can only be divided by -1 with overflow.
+    const MIN_BY_MINUS_ONE: &str = "root: gggg + humn\n\
+                                    gggg: ffff / neg\n\
+                                    ffff: eeee * four\n\
+                                    eeee: cccc * dddd\n\
+                                    cccc: -9223372036854775808\n\
+                                    dddd: 4611686018427387904\n\
+                                    four: 4\n\
+                                    neg: -1\n\
+                                    humn: 1";
+
+    fn eval_error(input: &str) -> (EvalErrorKind, String) {
+        let err = evaluate(input, "root").unwrap_err();
+        (err.kind, err.chain.join(" "))
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        let cycle = "root: aaaa + bbbb\naaaa: bbbb * cccc\nbbbb: 1\ncccc: aaaa - bbbb";
+        assert_eq!(
+            eval_error(cycle),
+            (EvalErrorKind::Cycle, "aaaa cccc aaaa".into())
+        );
+        let own = "root: root + one\none: 1";
+        assert_eq!(eval_error(own), (EvalErrorKind::Cycle, "root root".into()));
+
+        let undefined = "root: aaaa + one\naaaa: one * zzzz\none: 1";
+        assert_eq!(
+            eval_error(undefined),
+            (EvalErrorKind::Undefined, "root aaaa zzzz".into())
+        );
+        assert_eq!(
+            evaluate("one: 1", "root").unwrap_err().to_string(),
+            "Undefined monkey: root"
+        );
+
+        let overflow = "root: aaaa + one\naaaa: big * big\nbig: 4000000000\none: 1";
+        let err = evaluate(overflow, "root").unwrap_err();
+        assert_eq!(err.to_string(), "Overflow: root -> aaaa");
+        let err = evaluate("root: a / b\na: -9223372036854775808\nb: -1", "root").unwrap_err();
+        assert_eq!(err.to_string(), "Overflow: root");
+        assert_eq!(
+            part1(MIN_BY_MINUS_ONE).unwrap_err().kind,
+            EvalErrorKind::Overflow
+        );
+        assert_eq!(
+            part2(MIN_BY_MINUS_ONE),
+            Err(SolveError::Overflow("gggg".into()))
+        );
+
+        let inexact = "root: aaaa + one\naaaa: seven / two\nseven: 7\ntwo: 2\none: 1";
+        assert_eq!(
+            eval_error(inexact),
+            (EvalErrorKind::InexactDivision, "root aaaa".into())
+        );
+
+        let zero = "root: one / zero\none: 1\nzero: 0";
+        assert_eq!(
+            eval_error(zero),
+            (EvalErrorKind::DivisionByZero, "root".into())
+        );
     }
 }
@@ -29,4 +29,7 @@ pub mod day23;
 pub mod day24;
 pub mod day25;
 
+#[cfg(test)]
+mod test_rng;
+
 aoc_lib! { year = 2022 }